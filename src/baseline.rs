@@ -0,0 +1,115 @@
+use crate::{replay, MemUnit, Samples};
+use anyhow::Result;
+use std::path::Path;
+
+/// Mean usage of an earlier run, like of a system sitting idle, taken off every sample with `--baseline` to
+/// leave what the program itself adds.
+pub struct Baseline {
+    cpu: f32,
+    /// In bytes, whatever the unit of the run it's from.
+    mem: u64,
+    virt_mem: u64,
+    disk_read_rate: f64,
+    disk_write_rate: f64,
+}
+
+impl Baseline {
+    /// Reads any output `peek replay` does, averaging over the samples of every run in it.
+    pub fn load(path: &Path) -> Result<Self> {
+        let samples: Vec<Samples> = replay::load(path, MemUnit::Bytes)?
+            .into_iter()
+            .flat_map(|(_, samples)| samples)
+            .collect();
+
+        if samples.is_empty() {
+            anyhow::bail!("baseline `{}` has no samples", path.display());
+        }
+
+        let mean = |value: fn(&Samples) -> f64| {
+            samples.iter().map(value).sum::<f64>() / samples.len() as f64
+        };
+
+        Ok(Self {
+            cpu: mean(|s| f64::from(s.cpu)) as f32,
+            mem: mean(|s| s.mem.bytes as f64).round() as u64,
+            virt_mem: mean(|s| s.virt_mem.bytes as f64).round() as u64,
+            disk_read_rate: mean(|s| s.disk_read_rate),
+            disk_write_rate: mean(|s| s.disk_write_rate),
+        })
+    }
+
+    /// Takes the baseline off `sample`, with nothing going below 0.
+    pub fn subtract(&self, sample: &mut Samples) {
+        sample.cpu = (sample.cpu - self.cpu).max(0.0);
+        sample.cpu_smoothed = sample.cpu_smoothed.map(|cpu| (cpu - self.cpu).max(0.0));
+        sample.mem.bytes = sample.mem.bytes.saturating_sub(self.mem);
+        sample.virt_mem.bytes = sample.virt_mem.bytes.saturating_sub(self.virt_mem);
+        sample.disk_read_rate = (sample.disk_read_rate - self.disk_read_rate).max(0.0);
+        sample.disk_write_rate = (sample.disk_write_rate - self.disk_write_rate).max(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subtract_takes_the_baseline_off_without_going_below_0() {
+        let baseline = Baseline {
+            cpu: 5.0,
+            mem: 1000,
+            virt_mem: 4000,
+            disk_read_rate: 10.0,
+            disk_write_rate: 10.0,
+        };
+        let mut sample = Samples {
+            cpu: 12.5,
+            cpu_smoothed: Some(3.0),
+            disk_read_rate: 25.0,
+            disk_write_rate: 5.0,
+            ..Samples::default()
+        };
+        sample.mem.bytes = 3000;
+        sample.virt_mem.bytes = 2000;
+
+        baseline.subtract(&mut sample);
+
+        assert_eq!(sample.cpu, 7.5);
+        assert_eq!(sample.cpu_smoothed, Some(0.0));
+        assert_eq!(sample.mem.bytes, 2000);
+        assert_eq!(sample.virt_mem.bytes, 0);
+        assert_eq!(sample.disk_read_rate, 15.0);
+        assert_eq!(sample.disk_write_rate, 0.0);
+    }
+
+    #[test]
+    fn baseline_is_the_mean_of_its_samples() {
+        let path = std::env::temp_dir().join(format!("peek-test-{}.ndjson", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "{\"label\":\"idle\",\"sample\":0,\"cpu\":1,\"mem\":100}\n\
+             {\"label\":\"idle\",\"sample\":1,\"cpu\":3,\"mem\":301}\n",
+        )
+        .unwrap();
+
+        let baseline = Baseline::load(&path).unwrap();
+
+        assert_eq!(baseline.cpu, 2.0);
+        assert_eq!(baseline.mem, 201);
+    }
+
+    #[test]
+    fn baseline_without_samples_is_an_error() {
+        let path = std::env::temp_dir().join(format!("peek-test-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "[]").unwrap();
+
+        let Err(err) = Baseline::load(&path) else {
+            panic!("an empty baseline was loaded");
+        };
+
+        assert_eq!(
+            err.to_string(),
+            format!("baseline `{}` has no samples", path.display())
+        );
+    }
+}
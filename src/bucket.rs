@@ -0,0 +1,246 @@
+use crate::{Cgroup, MemLimit, Memory, Network, Samples, SystemUsage};
+use std::time::Duration;
+
+/// Rolls samples up into one per `bucket` of time since the first, for `--bucket`.
+///
+/// Each bucket is timestamped with its first sample, numbered from `0` and has `delta_ms` since the previous
+/// bucket. Values are aggregated as:
+/// - `cpu`, `cpu_smoothed`, `cpu_cores`, `system_cpu`, `power_watts` and every rate: the mean.
+/// - `mem`, `virt_mem`, `swap`, `cgroup_mem`, `mem_limit_pct`, `system_mem_used`, `threads`, `open_files` and
+///   `process_count`: the max, and `system_mem_available`: the min.
+/// - `disk_read` and `disk_write`: the bytes read and written within the bucket, the first also counting
+///   everything before it, so they add up to what the process did over the whole run.
+/// - Other cumulative counters, `net_rx`, `net_tx`, `ctx_switches` and `page_faults`: the last value, so the
+///   difference between buckets is what was used within one.
+/// - Everything else, like `nice`, `uptime_secs`, `phase` and the spread of `--average`: the last value.
+pub fn roll_up(samples: &[Samples], bucket: Duration) -> Vec<Samples> {
+    let Some(first) = samples.first() else {
+        return Vec::new();
+    };
+
+    let bucket = (bucket.as_millis() as u64).max(1);
+
+    // Timestamps are of the wall clock, which can step back, so a sample from before the first lands in the first
+    // bucket rather than wrapping around.
+    let mut buckets: Vec<Samples> = samples
+        .chunk_by(|a, b| {
+            a.timestamp.saturating_sub(first.timestamp) / bucket
+                == b.timestamp.saturating_sub(first.timestamp) / bucket
+        })
+        .enumerate()
+        .map(|(i, chunk)| aggregate(i as u64, chunk))
+        .collect();
+
+    // Between buckets now, rather than between the samples in them. The cumulative totals are still the last
+    // ones of each bucket here.
+    let previous: Vec<(u64, u64, u64)> = buckets
+        .iter()
+        .map(|bucket| (bucket.timestamp, bucket.disk_read, bucket.disk_write))
+        .collect();
+
+    for (bucket, (timestamp, disk_read, disk_write)) in buckets.iter_mut().skip(1).zip(previous) {
+        bucket.delta_ms = bucket.timestamp.saturating_sub(timestamp);
+        bucket.disk_read = bucket.disk_read.saturating_sub(disk_read);
+        bucket.disk_write = bucket.disk_write.saturating_sub(disk_write);
+    }
+
+    buckets
+}
+
+fn aggregate(sample: u64, chunk: &[Samples]) -> Samples {
+    let first = &chunk[0];
+    let last = &chunk[chunk.len() - 1];
+
+    let mean =
+        |value: &dyn Fn(&Samples) -> f64| chunk.iter().map(value).sum::<f64>() / chunk.len() as f64;
+
+    let max_memory = |value: fn(&Samples) -> Memory| Memory {
+        bytes: chunk
+            .iter()
+            .map(|s| value(s).bytes)
+            .max()
+            .unwrap_or_default(),
+        unit: first.mem.unit,
+    };
+
+    Samples {
+        uuid: first.uuid,
+        label: first.label.clone(),
+        phase: last.phase.clone(),
+        sample,
+        timestamp: first.timestamp,
+        delta_ms: 0,
+        pid: last.pid,
+        name: last.name.clone(),
+        uptime_secs: last.uptime_secs,
+        process_count: chunk
+            .iter()
+            .map(|s| s.process_count)
+            .max()
+            .unwrap_or_default(),
+        cpu: mean(&|s| f64::from(s.cpu)) as f32,
+        cpu_smoothed: first
+            .cpu_smoothed
+            .map(|_| mean(&|s| f64::from(s.cpu_smoothed.unwrap_or_default())) as f32),
+        mem: max_memory(|s| s.mem),
+        virt_mem: max_memory(|s| s.virt_mem),
+        swap: chunk
+            .iter()
+            .filter_map(|s| s.swap)
+            .max_by_key(|swap| swap.bytes),
+        threads: chunk.iter().filter_map(|s| s.threads).max(),
+        open_files: chunk.iter().filter_map(|s| s.open_files).max(),
+        ctx_switches: last.ctx_switches,
+        page_faults: last.page_faults,
+        priority: last.priority,
+        cgroup: last.cgroup.map(|cgroup| Cgroup {
+            cgroup_mem: Memory {
+                bytes: chunk
+                    .iter()
+                    .filter_map(|s| s.cgroup)
+                    .map(|cgroup| cgroup.cgroup_mem.bytes)
+                    .max()
+                    .unwrap_or_default(),
+                ..cgroup.cgroup_mem
+            },
+            ..cgroup
+        }),
+        limit: last.limit.map(|limit| MemLimit {
+            mem_limit_pct: chunk
+                .iter()
+                .filter_map(|s| s.limit)
+                .map(|limit| limit.mem_limit_pct)
+                .fold(0.0, f64::max),
+            ..limit
+        }),
+        disk_read: last.disk_read,
+        disk_write: last.disk_write,
+        disk_read_rate: mean(&|s| s.disk_read_rate),
+        disk_write_rate: mean(&|s| s.disk_write_rate),
+        network: last.network.map(|network| Network {
+            net_rx_rate: mean(&|s| s.network.map_or(0.0, |network| network.net_rx_rate)),
+            net_tx_rate: mean(&|s| s.network.map_or(0.0, |network| network.net_tx_rate)),
+            ..network
+        }),
+        power_watts: last
+            .power_watts
+            .map(|_| mean(&|s| s.power_watts.unwrap_or_default())),
+        system: last.system.map(|system| SystemUsage {
+            system_cpu: mean(&|s| s.system.map_or(0.0, |system| f64::from(system.system_cpu)))
+                as f32,
+            system_mem_used: Memory {
+                bytes: chunk
+                    .iter()
+                    .filter_map(|s| s.system)
+                    .map(|system| system.system_mem_used.bytes)
+                    .max()
+                    .unwrap_or_default(),
+                ..system.system_mem_used
+            },
+            system_mem_available: Memory {
+                bytes: chunk
+                    .iter()
+                    .filter_map(|s| s.system)
+                    .map(|system| system.system_mem_available.bytes)
+                    .min()
+                    .unwrap_or_default(),
+                ..system.system_mem_available
+            },
+            ..system
+        }),
+        spread: last.spread,
+        cpu_cores: first.cpu_cores.as_ref().map(|cores| {
+            (0..cores.len())
+                .map(|core| {
+                    mean(&|s| {
+                        s.cpu_cores
+                            .as_ref()
+                            .and_then(|cores| cores.get(core))
+                            .map_or(0.0, |&usage| f64::from(usage))
+                    })
+                })
+                .map(|usage| usage as f32)
+                .collect()
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sample `ms` after the first, having read `read` bytes so far.
+    fn sample(ms: u64, cpu: f32, mem: u64, read: u64) -> Samples {
+        Samples {
+            timestamp: 1_000_000 + ms,
+            cpu,
+            mem: Memory {
+                bytes: mem,
+                ..Memory::default()
+            },
+            disk_read: read,
+            disk_write: read * 2,
+            ..Samples::default()
+        }
+    }
+
+    #[test]
+    fn buckets_average_cpu_and_take_the_max_mem() {
+        let samples = [
+            sample(0, 10.0, 100, 0),
+            sample(500, 30.0, 300, 0),
+            sample(1000, 50.0, 200, 0),
+        ];
+        let buckets = roll_up(&samples, Duration::from_secs(1));
+
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].cpu, 20.0);
+        assert_eq!(buckets[0].mem.bytes, 300);
+        assert_eq!(buckets[1].cpu, 50.0);
+        assert_eq!(buckets[1].mem.bytes, 200);
+    }
+
+    #[test]
+    fn buckets_are_numbered_and_timed_from_their_first_sample() {
+        let samples = [
+            sample(0, 0.0, 0, 0),
+            sample(400, 0.0, 0, 0),
+            sample(1200, 0.0, 0, 0),
+            sample(2100, 0.0, 0, 0),
+        ];
+        let buckets = roll_up(&samples, Duration::from_secs(1));
+
+        assert_eq!(
+            buckets.iter().map(|b| b.sample).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert_eq!(
+            buckets.iter().map(|b| b.delta_ms).collect::<Vec<_>>(),
+            [0, 1200, 900]
+        );
+    }
+
+    #[test]
+    fn buckets_have_the_disk_io_within_them() {
+        let samples = [
+            sample(0, 0.0, 0, 100),
+            sample(500, 0.0, 0, 150),
+            sample(1000, 0.0, 0, 170),
+            sample(2000, 0.0, 0, 200),
+        ];
+        let buckets = roll_up(&samples, Duration::from_secs(1));
+        let read: Vec<u64> = buckets.iter().map(|b| b.disk_read).collect();
+        let written: Vec<u64> = buckets.iter().map(|b| b.disk_write).collect();
+
+        assert_eq!(read, [150, 20, 30]);
+        assert_eq!(written, [300, 40, 60]);
+        assert_eq!(read.iter().sum::<u64>(), 200);
+    }
+
+    #[test]
+    fn sample_from_before_the_first_lands_in_the_first_bucket() {
+        let samples = [sample(1000, 0.0, 0, 0), sample(0, 0.0, 0, 0)];
+
+        assert_eq!(roll_up(&samples, Duration::from_secs(1)).len(), 1);
+    }
+}
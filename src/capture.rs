@@ -0,0 +1,61 @@
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Output of the program kept with `--capture`, up to `--capture-limit` bytes.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct Captured {
+    /// Lossily decoded as UTF-8.
+    pub output: String,
+    /// Whether the program wrote more than the limit, past which it was discarded.
+    pub truncated: bool,
+}
+
+/// Drains one of the program's streams on a thread, so the program never blocks on a full pipe.
+pub struct Capture {
+    buffer: Arc<Mutex<(Vec<u8>, bool)>>,
+    reader: JoinHandle<()>,
+}
+
+impl Capture {
+    pub fn new(mut stream: impl Read + Send + 'static, limit: u64) -> Self {
+        let buffer = Arc::new(Mutex::new((Vec::new(), false)));
+        let shared = Arc::clone(&buffer);
+
+        let reader = std::thread::spawn(move || {
+            let mut chunk = [0; 8192];
+
+            // Stops at the end of the stream, once the program and anything it started have closed it.
+            while let Ok(read @ 1..) = stream.read(&mut chunk) {
+                let mut buffer = shared.lock().expect("capture lock poisoned");
+                let (output, truncated) = &mut *buffer;
+
+                let room = (limit as usize).saturating_sub(output.len());
+                output.extend_from_slice(&chunk[..read.min(room)]);
+                *truncated |= read > room;
+            }
+        });
+
+        Self { buffer, reader }
+    }
+
+    /// Takes what was captured so far, waiting a moment for the rest of a program that has exited.
+    ///
+    /// A program left running, or one that handed the stream on to something still running, loses whatever
+    /// it writes afterwards.
+    pub fn finish(self) -> Captured {
+        let deadline = Instant::now() + Duration::from_secs(1);
+
+        while !self.reader.is_finished() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let buffer = self.buffer.lock().expect("capture lock poisoned");
+
+        Captured {
+            output: String::from_utf8_lossy(&buffer.0).into_owned(),
+            truncated: buffer.1,
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Compare two JSON outputs of `peek`, printing how much each metric changed from the first to the second.
+#[derive(clap::Args)]
+pub struct Args {
+    /// Baseline run.
+    a: PathBuf,
+    /// Run compared against the baseline.
+    b: PathBuf,
+    /// Flag increases of more than this many percent as regressions, and exit with 1 if there are any.
+    #[arg(long)]
+    threshold: Option<f64>,
+}
+
+/// Just the fields compared, everything else in a sample is ignored.
+#[derive(serde::Deserialize)]
+struct Sample {
+    cpu: f64,
+    mem: f64,
+    disk_read: f64,
+    disk_write: f64,
+}
+
+/// Prints the comparison to stdout, returning whether any metric regressed beyond the threshold.
+pub fn run(args: &Args) -> Result<bool> {
+    let (a, a_unit) = load(&args.a)?;
+    let (b, b_unit) = load(&args.b)?;
+
+    if a_unit != b_unit {
+        anyhow::bail!(
+            "`{}` is in `{a_unit}` but `{}` is in `{b_unit}`, both need the same `--mem-unit`",
+            args.a.display(),
+            args.b.display()
+        );
+    }
+
+    let metrics = [
+        ("peak_cpu", peak(&a, |s| s.cpu), peak(&b, |s| s.cpu)),
+        ("mean_cpu", mean(&a, |s| s.cpu), mean(&b, |s| s.cpu)),
+        ("peak_mem", peak(&a, |s| s.mem), peak(&b, |s| s.mem)),
+        ("mean_mem", mean(&a, |s| s.mem), mean(&b, |s| s.mem)),
+        // Cumulative, so the last sample holds the total.
+        (
+            "disk_read",
+            last(&a, |s| s.disk_read),
+            last(&b, |s| s.disk_read),
+        ),
+        (
+            "disk_write",
+            last(&a, |s| s.disk_write),
+            last(&b, |s| s.disk_write),
+        ),
+    ];
+
+    println!("{:<12}{:>16}{:>16}{:>12}", "metric", "a", "b", "change");
+
+    let mut regressed = false;
+
+    for (name, a, b) in metrics {
+        let change = if a == b { 0.0 } else { (b - a) / a * 100.0 };
+
+        let regression = args.threshold.is_some_and(|threshold| change > threshold);
+        regressed |= regression;
+
+        println!(
+            "{name:<12}{a:>16.2}{b:>16.2}{:>12}{}",
+            format!("{change:+.1}%"),
+            if regression { "  regression" } else { "" }
+        );
+    }
+
+    Ok(regressed)
+}
+
+/// Reads the samples and memory unit of a single run, from either the current JSON or `--legacy-json`.
+fn load(path: &Path) -> Result<(Vec<Sample>, String)> {
+    let file = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read `{}`", path.display()))?;
+
+    let json: Value = serde_json::from_str(&file)
+        .with_context(|| format!("`{}` is not valid JSON", path.display()))?;
+
+    let (samples, unit) = match json {
+        Value::Object(mut report) => {
+            let unit = report
+                .get("metadata")
+                .and_then(|metadata| metadata.get("mem_unit"))
+                .and_then(Value::as_str)
+                .unwrap_or("bytes")
+                .to_owned();
+
+            (report.remove("samples").unwrap_or_default(), unit)
+        }
+        Value::Array(runs) if runs.iter().any(|run| run.get("metadata").is_some()) => {
+            anyhow::bail!(
+                "`{}` has {} runs, only a single run can be compared",
+                path.display(),
+                runs.len()
+            )
+        }
+        // Legacy output is just the samples, which were always in bytes.
+        samples => (samples, "bytes".to_owned()),
+    };
+
+    let samples: Vec<Sample> = serde_json::from_value(samples)
+        .with_context(|| format!("`{}` is not the JSON output of `peek`", path.display()))?;
+
+    if samples.is_empty() {
+        anyhow::bail!("`{}` has no samples to compare", path.display());
+    }
+
+    Ok((samples, unit))
+}
+
+fn peak(samples: &[Sample], metric: fn(&Sample) -> f64) -> f64 {
+    samples.iter().map(metric).fold(0.0, f64::max)
+}
+
+fn mean(samples: &[Sample], metric: fn(&Sample) -> f64) -> f64 {
+    samples.iter().map(metric).sum::<f64>() / samples.len() as f64
+}
+
+fn last(samples: &[Sample], metric: fn(&Sample) -> f64) -> f64 {
+    samples.last().map(metric).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A run whose samples have `cpu`, in the current shape with its memory in `unit`.
+    fn run_file(name: &str, unit: &str, cpu: &[f64]) -> PathBuf {
+        let samples: Vec<Value> = cpu
+            .iter()
+            .map(|cpu| serde_json::json!({"cpu": cpu, "mem": 100, "disk_read": 0, "disk_write": 0}))
+            .collect();
+        let report = serde_json::json!({"metadata": {"mem_unit": unit}, "samples": samples});
+
+        write(name, &report.to_string())
+    }
+
+    fn write(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("peek-test-{}-{name}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn compare(a: PathBuf, b: PathBuf, threshold: Option<f64>) -> Result<bool> {
+        run(&Args { a, b, threshold })
+    }
+
+    #[test]
+    fn increase_over_the_threshold_is_a_regression() {
+        let a = run_file("a.json", "bytes", &[10.0, 20.0]);
+        let b = run_file("b.json", "bytes", &[10.0, 30.0]);
+
+        assert!(compare(a.clone(), b.clone(), Some(10.0)).unwrap());
+        assert!(!compare(a.clone(), b.clone(), Some(60.0)).unwrap());
+        assert!(!compare(a, b, None).unwrap());
+    }
+
+    #[test]
+    fn decrease_is_not_a_regression() {
+        let a = run_file("a.json", "bytes", &[10.0, 30.0]);
+        let b = run_file("b.json", "bytes", &[10.0, 20.0]);
+
+        assert!(!compare(a, b, Some(0.0)).unwrap());
+    }
+
+    #[test]
+    fn runs_in_different_units_are_not_compared() {
+        let a = run_file("a.json", "bytes", &[10.0]);
+        let b = run_file("b.json", "mib", &[10.0]);
+
+        let err = compare(a, b, None).unwrap_err();
+
+        assert!(
+            err.to_string().contains("both need the same `--mem-unit`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn legacy_samples_are_loaded_in_bytes() {
+        let path = write(
+            "legacy.json",
+            r#"[{"cpu": 1.5, "mem": 2, "disk_read": 3, "disk_write": 4}]"#,
+        );
+        let (samples, unit) = load(&path).unwrap();
+
+        assert_eq!(unit, "bytes");
+        assert_eq!(samples[0].cpu, 1.5);
+    }
+
+    #[test]
+    fn only_a_single_run_with_samples_is_loaded() {
+        let several = write(
+            "several.json",
+            r#"[{"metadata": {}, "samples": []}, {"metadata": {}, "samples": []}]"#,
+        );
+        let Err(err) = load(&several) else {
+            panic!("`several.json` was loaded");
+        };
+
+        assert!(
+            err.to_string()
+                .ends_with("has 2 runs, only a single run can be compared"),
+            "{err}"
+        );
+
+        let empty = write("empty.json", r#"{"metadata": {}, "samples": []}"#);
+        let Err(err) = load(&empty) else {
+            panic!("`empty.json` was loaded");
+        };
+
+        assert!(
+            err.to_string().ends_with("has no samples to compare"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn peak_mean_and_last_of_a_metric() {
+        let samples: Vec<Sample> = [(5.0, 10.0), (15.0, 30.0)]
+            .map(|(cpu, disk_read)| Sample {
+                cpu,
+                mem: 0.0,
+                disk_read,
+                disk_write: 0.0,
+            })
+            .into();
+
+        assert_eq!(peak(&samples, |s| s.cpu), 15.0);
+        assert_eq!(mean(&samples, |s| s.cpu), 10.0);
+        assert_eq!(last(&samples, |s| s.disk_read), 30.0);
+    }
+}
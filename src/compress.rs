@@ -0,0 +1,124 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+#[derive(Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    #[default]
+    None,
+    /// Needs the `gzip` feature.
+    Gzip,
+    /// Needs the `zstd` feature.
+    Zstd,
+}
+
+impl Compression {
+    /// Bails when `peek` was built without the feature the compression needs.
+    pub fn check(self) -> Result<()> {
+        match self {
+            Self::Gzip if cfg!(not(feature = "gzip")) => {
+                anyhow::bail!("gzip compression needs `peek` to be built with the `gzip` feature")
+            }
+            Self::Zstd if cfg!(not(feature = "zstd")) => {
+                anyhow::bail!("zstd compression needs `peek` to be built with the `zstd` feature")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Extension appended to the default output file name, after the format's.
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Gzip => Some("gz"),
+            Self::Zstd => Some("zst"),
+        }
+    }
+
+    /// Wraps `file` in the encoder, which finishes the stream once dropped.
+    ///
+    /// Appending adds another gzip member or zstd frame, both of which decompress as one concatenated stream.
+    pub fn writer(self, file: File) -> Result<Box<dyn Write>> {
+        let file = BufWriter::new(file);
+
+        Ok(match self {
+            Self::None => Box::new(file),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => Box::new(flate2::write::GzEncoder::new(
+                file,
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Box::new(zstd::Encoder::new(file, 0)?.auto_finish()),
+            // Rejected by `check`.
+            #[allow(unreachable_patterns)]
+            _ => unreachable!("compression is only used when its feature is enabled"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    fn file() -> PathBuf {
+        std::env::temp_dir().join(format!("peek-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    /// Writes each of `writes` through its own writer appending to `path`, like several runs with `--append`.
+    fn write(compression: Compression, path: &Path, writes: &[&str]) {
+        for text in writes {
+            let file = File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+                .unwrap();
+            let mut writer = compression.writer(file).unwrap();
+            writer.write_all(text.as_bytes()).unwrap();
+        }
+    }
+
+    #[test]
+    fn none_writes_as_is() {
+        let path = file();
+        write(Compression::None, &path, &["a\n", "b\n"]);
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nb\n");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn appended_gzip_decompresses_as_one_stream() {
+        use std::io::Read;
+
+        let path = file();
+        write(Compression::Gzip, &path, &["a\n", "b\n"]);
+
+        let mut text = String::new();
+        flate2::read::MultiGzDecoder::new(File::open(&path).unwrap())
+            .read_to_string(&mut text)
+            .unwrap();
+
+        assert_eq!(text, "a\nb\n");
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn appended_zstd_decompresses_as_one_stream() {
+        let path = file();
+        write(Compression::Zstd, &path, &["a\n", "b\n"]);
+
+        let bytes = zstd::decode_all(File::open(&path).unwrap()).unwrap();
+
+        assert_eq!(bytes, b"a\nb\n");
+    }
+
+    #[test]
+    fn compression_without_its_feature_is_rejected() {
+        assert!(Compression::None.check().is_ok());
+        assert_eq!(Compression::Gzip.check().is_ok(), cfg!(feature = "gzip"));
+        assert_eq!(Compression::Zstd.check().is_ok(), cfg!(feature = "zstd"));
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{Cli, CpuScale, Format, MemUnit, Output};
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgMatches, CommandFactory};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Options loaded from a TOML file with `--config`, so a profiling setup can be checked in and rerun.
+///
+/// Anything also given on the command line takes precedence.
+#[derive(Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    program: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    path: Option<PathBuf>,
+    output: Option<Output>,
+    format: Option<Format>,
+    label: Option<String>,
+    interval: Option<u64>,
+    duration: Option<u64>,
+    max_samples: Option<u64>,
+    tree: Option<bool>,
+    summary: Option<bool>,
+    per_core: Option<bool>,
+    system: Option<bool>,
+    cgroup: Option<bool>,
+    #[serde(default)]
+    columns: Vec<String>,
+    mem_unit: Option<MemUnit>,
+    cpu_scale: Option<CpuScale>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    clear_env: Option<bool>,
+    cwd: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let config = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config `{}`", path.display()))?;
+
+        toml::from_str(&config)
+            .with_context(|| format!("failed to parse config `{}`", path.display()))
+    }
+
+    /// Fills in every option of `cli` that wasn't given on the command line, then checks that what the config
+    /// filled in doesn't conflict with anything else given, which clap could only check for the command line.
+    pub fn apply(self, cli: &mut Cli, matches: &ArgMatches) -> Result<()> {
+        let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+        // Ids of the options filled in from the config.
+        let mut applied = Vec::new();
+
+        if cli.program.is_empty()
+            && cli.pid.is_none()
+            && cli.name.is_none()
+            && cli.pattern.is_none()
+        {
+            if let Some(program) = self.program {
+                cli.program = std::iter::once(program).chain(self.args).collect();
+                applied.push("program");
+            }
+        }
+
+        if let Some(output) = self.output.filter(|_| !given("output")) {
+            cli.output = output;
+            applied.push("output");
+        }
+
+        if let Some(format) = self.format.filter(|_| !given("format")) {
+            cli.format = format;
+            applied.push("format");
+        }
+
+        if let Some(cpu_scale) = self.cpu_scale.filter(|_| !given("cpu_scale")) {
+            cli.cpu_scale = cpu_scale;
+            applied.push("cpu_scale");
+        }
+
+        fill(&mut applied, "path", &mut cli.path, self.path);
+        fill(&mut applied, "label", &mut cli.label, self.label);
+        fill(&mut applied, "interval", &mut cli.interval, self.interval);
+        fill(&mut applied, "duration", &mut cli.duration, self.duration);
+        fill(
+            &mut applied,
+            "max_samples",
+            &mut cli.max_samples,
+            self.max_samples,
+        );
+        fill(&mut applied, "cwd", &mut cli.cwd, self.cwd);
+        fill(&mut applied, "mem_unit", &mut cli.mem_unit, self.mem_unit);
+
+        if cli.columns.is_empty() && !self.columns.is_empty() {
+            cli.columns = self.columns;
+            applied.push("columns");
+        }
+
+        for (id, flag, value) in [
+            ("tree", &mut cli.tree, self.tree),
+            ("summary", &mut cli.summary, self.summary),
+            ("per_core", &mut cli.per_core, self.per_core),
+            ("system", &mut cli.system, self.system),
+            ("cgroup", &mut cli.cgroup, self.cgroup),
+            ("clear_env", &mut cli.clear_env, self.clear_env),
+        ] {
+            if value.unwrap_or_default() && !*flag {
+                *flag = true;
+                applied.push(id);
+            }
+        }
+
+        // Applied in order, so putting the command line's last lets it override the config.
+        let env = std::mem::take(&mut cli.env);
+        cli.env = self.env.into_iter().chain(env).collect();
+
+        conflicts(matches, &applied)
+    }
+}
+
+/// Fills in `value` with the config's when it wasn't given, noting `id` among those `applied`.
+fn fill<T>(
+    applied: &mut Vec<&'static str>,
+    id: &'static str,
+    value: &mut Option<T>,
+    config: Option<T>,
+) {
+    if value.is_none() && config.is_some() {
+        *value = config;
+        applied.push(id);
+    }
+}
+
+/// Bails when an option `applied` from the config conflicts with one given on the command line.
+fn conflicts(matches: &ArgMatches, applied: &[&str]) -> Result<()> {
+    let command = Cli::command();
+    let find = |id: &str| command.get_arguments().find(|arg| arg.get_id() == id);
+
+    let given: Vec<&Arg> = matches
+        .ids()
+        .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+        .filter_map(|id| find(id.as_str()))
+        .collect();
+    let applied: Vec<&Arg> = applied.iter().filter_map(|id| find(id)).collect();
+
+    let conflict = |a: &Arg, b: &Arg| {
+        command.get_arg_conflicts_with(a).contains(&b)
+            || command.get_arg_conflicts_with(b).contains(&a)
+    };
+
+    for arg in applied {
+        if let Some(other) = given.iter().find(|other| conflict(arg, other)) {
+            let other = other
+                .get_long()
+                .map_or_else(|| other.get_id().to_string(), |long| format!("--{long}"));
+
+            anyhow::bail!(
+                "`{}` from the config can't be used with `{other}`",
+                arg.get_id()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::FromArgMatches;
+
+    /// `args` after `peek --config` with `config` applied.
+    fn applied(config: &str, args: &[&str]) -> Result<Cli> {
+        let matches = Cli::command()
+            .try_get_matches_from(["peek", "--config", "peek.toml"].iter().chain(args))?;
+        let mut cli = Cli::from_arg_matches(&matches)?;
+
+        toml::from_str::<Config>(config)?.apply(&mut cli, &matches)?;
+
+        Ok(cli)
+    }
+
+    #[test]
+    fn config_picks_the_metrics() {
+        let config = r#"
+            program = "sleep"
+            args = ["1"]
+            per_core = true
+            system = true
+            cgroup = true
+            columns = ["cpu", "mem"]
+            mem_unit = "mib"
+            cpu_scale = "system"
+        "#;
+        let cli = applied(config, &[]).unwrap();
+
+        assert_eq!(cli.program, ["sleep", "1"]);
+        assert!(cli.per_core && cli.system && cli.cgroup);
+        assert_eq!(cli.columns, ["cpu", "mem"]);
+        assert!(cli.mem_unit == Some(MemUnit::Mib));
+        assert!(cli.cpu_scale == CpuScale::System);
+    }
+
+    #[test]
+    fn command_line_overrides_the_config() {
+        let config = r#"
+            program = "sleep"
+            mem_unit = "mib"
+            cpu_scale = "system"
+            columns = ["cpu"]
+        "#;
+        let args = [
+            "--mem-unit",
+            "kib",
+            "--cpu-scale",
+            "core",
+            "--columns",
+            "mem",
+            "true",
+        ];
+        let cli = applied(config, &args).unwrap();
+
+        assert_eq!(cli.program, ["true"]);
+        assert!(cli.mem_unit == Some(MemUnit::Kib));
+        assert!(cli.cpu_scale == CpuScale::Core);
+        assert_eq!(cli.columns, ["mem"]);
+    }
+
+    #[test]
+    fn config_conflicting_with_the_command_line_is_an_error() {
+        let Err(err) = applied("program = \"sleep\"", &["--cmd", "true"]) else {
+            panic!("the config's program was run along with --cmd");
+        };
+
+        assert_eq!(
+            err.to_string(),
+            "`program` from the config can't be used with `--cmd`"
+        );
+
+        let Err(err) = applied("tree = true", &["--match", "sleep"]) else {
+            panic!("the config's tree was used with --match");
+        };
+
+        assert_eq!(
+            err.to_string(),
+            "`tree` from the config can't be used with `--match`"
+        );
+    }
+}
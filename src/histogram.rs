@@ -0,0 +1,141 @@
+use crate::{MemUnit, Samples};
+
+/// Longest bar drawn, for the fullest bin.
+const WIDTH: u64 = 40;
+
+/// How the samples of a run are spread between their lowest and highest value, for `--histogram`.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct Histogram {
+    pub min: f64,
+    pub max: f64,
+    /// Samples in each of the equally wide bins from `min` to `max`, the last including `max` itself.
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    fn new(values: &[f64], bins: usize) -> Self {
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+        let mut counts = vec![0; bins];
+
+        if values.is_empty() {
+            return Self {
+                min: 0.0,
+                max: 0.0,
+                counts,
+            };
+        }
+
+        let width = (max - min) / bins as f64;
+
+        for value in values {
+            // Everything falls in the first bin when all values are the same.
+            let bin = if width == 0.0 {
+                0
+            } else {
+                (((value - min) / width) as usize).min(bins - 1)
+            };
+
+            counts[bin] += 1;
+        }
+
+        Self { min, max, counts }
+    }
+
+    /// Prints a bar per bin to stderr, each labeled with its range shown with `precision` decimals.
+    fn print(&self, name: &str, precision: usize, scale: impl Fn(f64) -> f64) {
+        eprintln!("{name}");
+
+        let fullest = self.counts.iter().copied().max().unwrap_or_default().max(1);
+        let width = (self.max - self.min) / self.counts.len() as f64;
+
+        for (i, &count) in self.counts.iter().enumerate() {
+            let from = scale(self.min + width * i as f64);
+            let to = scale(self.min + width * (i + 1) as f64);
+            let bar = "#".repeat((count * WIDTH).div_ceil(fullest) as usize);
+
+            eprintln!("{from:>14.precision$} - {to:<14.precision$} {bar:<40} {count}");
+        }
+    }
+}
+
+/// Histograms of `cpu` and `mem`, included in the metadata with `--histogram`.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct Histograms {
+    pub cpu: Histogram,
+    /// In bytes whatever the `mem_unit`.
+    pub mem: Histogram,
+}
+
+impl Histograms {
+    pub fn new(samples: &[Samples], bins: usize) -> Self {
+        let cpu: Vec<f64> = samples.iter().map(|s| f64::from(s.cpu)).collect();
+        let mem: Vec<f64> = samples.iter().map(|s| s.mem.bytes as f64).collect();
+
+        Self {
+            cpu: Histogram::new(&cpu, bins),
+            mem: Histogram::new(&mem, bins),
+        }
+    }
+
+    /// Prints both to stderr, with memory in `unit`.
+    pub fn print(&self, unit: MemUnit) {
+        self.cpu.print("cpu", 2, |cpu| cpu);
+        eprintln!();
+
+        let name = match unit {
+            MemUnit::Bytes => "mem".to_owned(),
+            unit => format!("mem ({})", unit.suffix()),
+        };
+        let precision = if unit == MemUnit::Bytes { 0 } else { 2 };
+
+        self.mem
+            .print(&name, precision, |bytes| unit.convert(bytes as u64));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_bins_values_between_min_and_max() {
+        let histogram = Histogram::new(&[0.0, 1.0, 2.5, 5.0, 7.4, 10.0], 4);
+
+        assert_eq!((histogram.min, histogram.max), (0.0, 10.0));
+        // The highest value is counted in the last bin rather than one past it.
+        assert_eq!(histogram.counts, [2, 1, 2, 1]);
+    }
+
+    #[test]
+    fn histogram_of_equal_values_fills_the_first_bin() {
+        assert_eq!(Histogram::new(&[3.0, 3.0, 3.0], 5).counts, [3, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn histogram_of_nothing_is_empty() {
+        let histogram = Histogram::new(&[], 3);
+
+        assert_eq!((histogram.min, histogram.max), (0.0, 0.0));
+        assert_eq!(histogram.counts, [0, 0, 0]);
+    }
+
+    #[test]
+    fn histograms_of_mem_are_in_bytes() {
+        let samples: Vec<Samples> = [1024, 2048]
+            .into_iter()
+            .map(|bytes| Samples {
+                mem: crate::Memory {
+                    bytes,
+                    unit: MemUnit::Mib,
+                },
+                ..Samples::default()
+            })
+            .collect();
+        let histograms = Histograms::new(&samples, 2);
+
+        assert_eq!((histograms.mem.min, histograms.mem.max), (1024.0, 2048.0));
+        assert_eq!(histograms.mem.counts, [1, 1]);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::Report;
+use anyhow::Result;
+
+/// Renders a self-contained HTML report charting CPU and memory over each run, overlaid on one chart.
+///
+/// The samples are embedded as JSON and drawn with a small canvas script, so the page works offline.
+pub fn render(reports: &[Report]) -> Result<String> {
+    let data = serde_json::to_string(reports)?
+        // A literal `</script>` inside a process name would otherwise end the script block early.
+        .replace("</", "<\\/");
+
+    Ok(TEMPLATE.replace("{{DATA}}", &data))
+}
+
+const TEMPLATE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>peek report</title>
+<style>
+  body { font-family: system-ui, sans-serif; margin: 2rem; color: #222; }
+  h1 { font-size: 1.4rem; }
+  .chart { position: relative; margin-bottom: 2rem; }
+  canvas { width: 100%; height: 280px; border: 1px solid #ddd; }
+  .tooltip { position: absolute; pointer-events: none; background: #222; color: #fff;
+             padding: 2px 6px; font-size: 12px; border-radius: 3px; display: none; }
+  dl { display: grid; grid-template-columns: max-content auto; gap: 2px 1rem; }
+  dt { font-weight: bold; }
+  .legend span { margin-right: 1rem; }
+</style>
+</head>
+<body>
+<h1>peek report</h1>
+<div id="metadata"></div>
+<p class="legend" id="legend"></p>
+<div class="chart"><h2 id="cpu-title">CPU (%)</h2><canvas id="cpu"></canvas><div class="tooltip"></div></div>
+<div class="chart"><h2 id="mem-title">Memory</h2><canvas id="mem"></canvas><div class="tooltip"></div></div>
+<script>
+const DATA = {{DATA}};
+
+const colors = ["#d9480f", "#1971c2", "#2f9e44", "#9c36b5", "#f08c00", "#0c8599"];
+const color = (run) => colors[run % colors.length];
+
+const metadata = document.getElementById("metadata");
+for (const report of DATA) {
+  const title = document.createElement("h3");
+  const dl = document.createElement("dl");
+  title.textContent = report.metadata.label;
+  for (const [key, value] of Object.entries(report.metadata)) {
+    const dt = document.createElement("dt");
+    const dd = document.createElement("dd");
+    dt.textContent = key;
+    dd.textContent = value === null ? "-" : JSON.stringify(value);
+    dl.append(dt, dd);
+  }
+  metadata.append(title, dl);
+}
+
+const legend = document.getElementById("legend");
+if (DATA.length > 1) {
+  DATA.forEach((report, run) => {
+    const span = document.createElement("span");
+    span.style.color = color(run);
+    span.textContent = "\u25A0 " + report.metadata.label;
+    legend.append(span);
+  });
+}
+
+const units = { bytes: "bytes", kib: "KiB", mib: "MiB", gib: "GiB" };
+const unit = DATA.length ? DATA[0].metadata.mem_unit : "bytes";
+document.getElementById("mem-title").textContent = "Memory (" + units[unit] + ")";
+
+// Each run's time starts at its own first sample, so runs line up for comparison.
+const seconds = DATA.map((report) => {
+  const start = report.samples.length ? report.samples[0].timestamp : 0;
+  return report.samples.map((s) => (s.timestamp - start) / 1000);
+});
+
+function chart(id, metric) {
+  const canvas = document.getElementById(id);
+  const tooltip = canvas.nextElementSibling;
+  const ctx = canvas.getContext("2d");
+  const pad = { left: 80, right: 16, top: 12, bottom: 28 };
+  const series = DATA.map((report) => report.samples.map(metric));
+
+  function layout() {
+    const ratio = window.devicePixelRatio || 1;
+    canvas.width = canvas.clientWidth * ratio;
+    canvas.height = canvas.clientHeight * ratio;
+    ctx.setTransform(ratio, 0, 0, ratio, 0, 0);
+  }
+
+  const width = () => canvas.clientWidth - pad.left - pad.right;
+  const height = () => canvas.clientHeight - pad.top - pad.bottom;
+  // Spreading a long run into Math.max would go over the engine's argument limit.
+  const max = (values) => values.reduce((max, value) => (value > max ? value : max), 1);
+  const maxX = max(seconds.flat());
+  const maxY = max(series.flat());
+  const x = (value) => pad.left + (value / maxX) * width();
+  const y = (value) => pad.top + height() - (value / maxY) * height();
+
+  function draw() {
+    layout();
+    ctx.clearRect(0, 0, canvas.clientWidth, canvas.clientHeight);
+
+    ctx.strokeStyle = "#999";
+    ctx.beginPath();
+    ctx.moveTo(pad.left, pad.top);
+    ctx.lineTo(pad.left, pad.top + height());
+    ctx.lineTo(pad.left + width(), pad.top + height());
+    ctx.stroke();
+
+    ctx.fillStyle = "#555";
+    ctx.font = "12px system-ui, sans-serif";
+    ctx.textAlign = "right";
+    ctx.fillText(maxY.toLocaleString(), pad.left - 6, pad.top + 10);
+    ctx.fillText("0", pad.left - 6, pad.top + height());
+    ctx.textAlign = "center";
+    ctx.fillText("0s", pad.left, pad.top + height() + 18);
+    ctx.fillText(maxX.toFixed(1) + "s", pad.left + width(), pad.top + height() + 18);
+
+    series.forEach((values, run) => {
+      ctx.strokeStyle = color(run);
+      ctx.lineWidth = 1.5;
+      ctx.beginPath();
+      values.forEach((value, i) => {
+        if (i === 0) ctx.moveTo(x(seconds[run][i]), y(value));
+        else ctx.lineTo(x(seconds[run][i]), y(value));
+      });
+      ctx.stroke();
+    });
+  }
+
+  canvas.addEventListener("mousemove", (event) => {
+    const target = ((event.offsetX - pad.left) / width()) * maxX;
+    const lines = [];
+    let top = null;
+    series.forEach((values, run) => {
+      if (!values.length) return;
+      let nearest = 0;
+      seconds[run].forEach((value, i) => {
+        if (Math.abs(value - target) < Math.abs(seconds[run][nearest] - target)) nearest = i;
+      });
+      const prefix = DATA.length > 1 ? DATA[run].metadata.label + ", " : "";
+      lines.push(
+        prefix + "sample " + DATA[run].samples[nearest].sample + " @ " +
+        seconds[run][nearest].toFixed(1) + "s: " + values[nearest].toLocaleString()
+      );
+      if (top === null) top = y(values[nearest]);
+    });
+    if (!lines.length) return;
+    tooltip.style.display = "block";
+    tooltip.style.whiteSpace = "pre";
+    tooltip.style.left = event.offsetX + 8 + "px";
+    tooltip.style.top = canvas.offsetTop + top + "px";
+    tooltip.textContent = lines.join("\n");
+  });
+  canvas.addEventListener("mouseleave", () => (tooltip.style.display = "none"));
+  window.addEventListener("resize", draw);
+  draw();
+}
+
+// With `--smooth` the smoothed value reads far better than the spiky raw one.
+const smoothed = DATA.some((report) => report.samples.some((s) => s.cpu_smoothed !== undefined));
+if (smoothed) document.getElementById("cpu-title").textContent = "CPU (%, smoothed)";
+
+chart("cpu", (s) => (smoothed ? s.cpu_smoothed : s.cpu));
+chart("mem", (s) => s.mem);
+</script>
+</body>
+</html>
+"##;
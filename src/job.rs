@@ -0,0 +1,103 @@
+//! Windows Job Objects, which account for every process a spawned program starts.
+//!
+//! Walking the tree by parent pid misses children whose parent already exited, which Windows doesn't
+//! reparent, so `--tree` undercounts there. Everything started from within a job stays in it instead.
+//! Only Windows is supported, on other platforms no job is ever created.
+
+use std::process::Child;
+use std::time::Duration;
+
+/// Totals across every process in the job, including those that already exited.
+#[derive(Debug, Copy, Clone)]
+pub struct Accounting {
+    /// User and kernel time combined.
+    pub cpu_time: Duration,
+    pub read_bytes: u64,
+    pub written_bytes: u64,
+    pub active_processes: usize,
+}
+
+#[cfg(windows)]
+pub struct Job(windows_sys::Win32::Foundation::HANDLE);
+
+#[cfg(windows)]
+impl Job {
+    /// Puts `child` in a new job. Anything it started before being assigned isn't part of it, which is only
+    /// the moment right after spawning.
+    pub fn new(child: &Child) -> Option<Self> {
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::System::JobObjects::{AssignProcessToJobObject, CreateJobObjectW};
+
+        // SAFETY: Both handles are valid, the job's is closed again on drop and the child's is owned by it.
+        unsafe {
+            let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+
+            if job.is_null() {
+                return None;
+            }
+
+            let job = Self(job);
+
+            (AssignProcessToJobObject(job.0, child.as_raw_handle()) != 0).then_some(job)
+        }
+    }
+
+    pub fn accounting(&self) -> Option<Accounting> {
+        use windows_sys::Win32::System::JobObjects::{
+            JobObjectBasicAndIoAccountingInformation, QueryInformationJobObject,
+            JOBOBJECT_BASIC_AND_IO_ACCOUNTING_INFORMATION,
+        };
+
+        let mut info = JOBOBJECT_BASIC_AND_IO_ACCOUNTING_INFORMATION::default();
+
+        // SAFETY: `info` is the struct this information class fills in, and its size is passed along.
+        let queried = unsafe {
+            QueryInformationJobObject(
+                self.0,
+                JobObjectBasicAndIoAccountingInformation,
+                std::ptr::addr_of_mut!(info).cast(),
+                std::mem::size_of_val(&info) as u32,
+                std::ptr::null_mut(),
+            )
+        };
+
+        if queried == 0 {
+            return None;
+        }
+
+        // Times are counted in 100ns ticks.
+        let ticks = info.BasicInfo.TotalUserTime + info.BasicInfo.TotalKernelTime;
+
+        Some(Accounting {
+            cpu_time: Duration::from_nanos(ticks as u64 * 100),
+            read_bytes: info.IoInfo.ReadTransferCount,
+            written_bytes: info.IoInfo.WriteTransferCount,
+            active_processes: info.BasicInfo.ActiveProcesses as usize,
+        })
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Job {
+    fn drop(&mut self) {
+        // SAFETY: The handle was created by `new` and isn't used after this. Closing it leaves the processes
+        // in it running.
+        unsafe {
+            windows_sys::Win32::Foundation::CloseHandle(self.0);
+        }
+    }
+}
+
+#[cfg(not(windows))]
+pub struct Job;
+
+#[cfg(not(windows))]
+impl Job {
+    pub fn new(_child: &Child) -> Option<Self> {
+        None
+    }
+
+    pub fn accounting(&self) -> Option<Accounting> {
+        None
+    }
+}
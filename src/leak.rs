@@ -0,0 +1,133 @@
+use crate::Samples;
+
+/// Fewer samples than this are too few to tell a trend from noise.
+pub const MIN_SAMPLES: usize = 10;
+
+/// How well `mem` fits growing at a steady rate, from a least squares fit over time.
+pub struct Trend {
+    /// Bytes per second `mem` grows by, negative if it shrinks.
+    pub slope: f64,
+    /// Coefficient of determination, from `0` for no linear trend at all to `1` for a perfect line.
+    pub r_squared: f64,
+    /// Seconds from the first sample fitted to the last.
+    pub duration: f64,
+}
+
+impl Trend {
+    /// A steady climb, close to a line, growing by over `threshold` bytes across the run.
+    pub fn is_leak(&self, threshold: u64) -> bool {
+        self.r_squared >= 0.8 && self.slope * self.duration > threshold as f64
+    }
+}
+
+/// Fits `mem` against time, `None` when there are under `MIN_SAMPLES` to go on.
+///
+/// Samples with no `mem` at all are left out, those are of a process that was only just spawned and would
+/// read as steep growth.
+pub fn trend(samples: &[Samples]) -> Option<Trend> {
+    let samples: Vec<&Samples> = samples.iter().filter(|s| s.mem.bytes > 0).collect();
+
+    if samples.len() < MIN_SAMPLES {
+        return None;
+    }
+
+    let start = samples[0].timestamp;
+    let points: Vec<(f64, f64)> = samples
+        .iter()
+        .map(|s| {
+            (
+                s.timestamp.saturating_sub(start) as f64 / 1000.0,
+                s.mem.bytes as f64,
+            )
+        })
+        .collect();
+
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+
+    let covariance: f64 = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let variance_x: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let variance_y: f64 = points.iter().map(|(_, y)| (y - mean_y).powi(2)).sum();
+
+    let slope = if variance_x == 0.0 {
+        0.0
+    } else {
+        covariance / variance_x
+    };
+
+    // Flat memory fits a flat line perfectly, but it's no trend.
+    let r_squared = if variance_x == 0.0 || variance_y == 0.0 {
+        0.0
+    } else {
+        covariance.powi(2) / (variance_x * variance_y)
+    };
+
+    Some(Trend {
+        slope,
+        r_squared,
+        duration: points[points.len() - 1].0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Memory;
+
+    /// A sample every second from `0`, with `mem` in bytes.
+    fn samples(mem: impl IntoIterator<Item = u64>) -> Vec<Samples> {
+        mem.into_iter()
+            .enumerate()
+            .map(|(i, bytes)| Samples {
+                timestamp: 1_000_000 + i as u64 * 1000,
+                mem: Memory {
+                    bytes,
+                    ..Memory::default()
+                },
+                ..Samples::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn steady_growth_is_a_leak() {
+        let trend = trend(&samples((1..=20).map(|i| i * 1_000_000))).unwrap();
+
+        assert!((trend.slope - 1_000_000.0).abs() < 1e-6);
+        assert!((trend.r_squared - 1.0).abs() < 1e-9);
+        assert!(trend.is_leak(10_000_000));
+        assert!(!trend.is_leak(20_000_000));
+    }
+
+    #[test]
+    fn flat_memory_is_no_leak() {
+        let trend = trend(&samples([5_000_000; 20])).unwrap();
+
+        assert_eq!(trend.r_squared, 0.0);
+        assert!(!trend.is_leak(0));
+    }
+
+    #[test]
+    fn too_few_samples_have_no_trend() {
+        assert!(trend(&samples([1; MIN_SAMPLES - 1])).is_none());
+    }
+
+    #[test]
+    fn samples_without_mem_are_left_out() {
+        let mem = std::iter::once(0).chain((1..=MIN_SAMPLES as u64).map(|i| i * 1000));
+
+        assert!((trend(&samples(mem)).unwrap().slope - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn clock_stepping_back_doesnt_underflow() {
+        let mut samples = samples((1..=20).map(|i| i * 1000));
+        samples[5].timestamp = 0;
+
+        assert!(trend(&samples).is_some());
+    }
+}
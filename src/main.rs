@@ -1,247 +1,4091 @@
 // PID,NAME,CPU,MEM,VIRT_MEM
 
+mod baseline;
+mod bucket;
+mod capture;
+mod compare;
+mod compress;
+mod config;
+mod histogram;
+mod html;
+mod job;
+mod leak;
+mod markdown;
+mod markers;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod procfs;
+mod prometheus;
+#[cfg(feature = "push")]
+mod push;
+#[cfg(feature = "remote")]
+mod remote;
+mod repeat;
+mod replay;
+mod spawns;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod summary;
+mod top;
+mod watch;
+
 use anyhow::Context;
 use anyhow::Result;
-use clap::{Parser, ValueEnum};
+use capture::{Capture, Captured};
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
+use compress::Compression;
+use histogram::Histograms;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::ffi::OsStr;
 use std::fmt::Display;
 use std::fs::File;
 use std::io::Write;
-use std::process::ExitStatus;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::process::{ExitStatus, Stdio};
 use std::sync::mpsc::channel;
 use std::sync::mpsc::Receiver;
-use std::{path::PathBuf, process::Command};
-use sysinfo::{ProcessExt, System, SystemExt};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use sysinfo::{CpuExt, Pid, Process, ProcessExt, ProcessRefreshKind, Signal, System, SystemExt};
 use uuid::Uuid;
 
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let mut matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+    match &cli.command {
+        Some(Subcommand::Compare(args)) => {
+            if compare::run(args)? {
+                std::process::exit(1);
+            }
+
+            return Ok(());
+        }
+        Some(Subcommand::Replay(args)) => return replay::run(args),
+        Some(Subcommand::Top(_)) | None => {}
+    }
+
+    if let Some(Subcommand::Top(args)) = &cli.command {
+        let Some(pid) = top::run(args)? else {
+            return Ok(());
+        };
+
+        // Carries on as if started with `peek --pid <PID> --watch`.
+        matches = Cli::command().get_matches_from(["peek", "--pid", &pid.to_string(), "--watch"]);
+        cli = Cli::from_arg_matches(&matches)?;
+    }
+
+    if cli.print_schema {
+        // Describes what gets written, not everything `peek replay` accepts.
+        let schema = schemars::generate::SchemaSettings::default()
+            .for_serialize()
+            .into_generator()
+            .into_root_schema_for::<Report>();
+        println!("{}", serde_json::to_string_pretty(&schema)?);
+
+        return Ok(());
+    }
+
+    if let Some(path) = &cli.config {
+        config::Config::load(path)?.apply(&mut cli, &matches)?;
+    }
+
+    #[cfg(feature = "remote")]
+    if let Some(host) = &cli.remote {
+        if let Some(code) = remote::run(host, &cli, &matches)? {
+            std::process::exit(code);
+        }
+
+        return Ok(());
+    }
+
+    let stderr_output = cli.output == Output::Stderr && !cli.no_output;
 
-    let mut peek = Peek::new(cli)?;
+    let (tx, rx) = channel();
+    // Sending only fails once sampling is over, when there's nothing left to interrupt.
+    ctrlc::set_handler(move || {
+        let _ = tx.send(());
+    })?;
+
+    let mut peek = Peek::new(cli, rx)?;
+
+    if peek.dry_run {
+        return peek.plan();
+    }
 
     peek.run()?;
+    peek.print_collected();
+
+    let exit_code = peek.exit_code();
+    let leaked = peek.detect_leaks();
+    let exceeded = peek.exceeded_limits();
+
+    peek.report();
+
+    if !peek.no_output {
+        peek.output()?;
+    }
+
+    // Only reported after the data, set apart from it when `--output stderr` wrote it to the same place.
+    if stderr_output && !exceeded.is_empty() {
+        eprintln!();
+    }
+
+    for error in &exceeded {
+        eprintln!("error: {error}");
+    }
+
+    if !exceeded.is_empty() || leaked {
+        std::process::exit(1);
+    }
 
-    peek.output()?;
+    if let Some(code) = exit_code {
+        std::process::exit(code);
+    }
 
     Ok(())
 }
 
 struct Peek {
     system: System,
-    program: Program,
-    output_path: PathBuf,
+    /// Only refreshed for CPU usage, every `MINIMUM_CPU_UPDATE_INTERVAL`, when `--interval` is shorter than that.
+    cpu_system: Option<System>,
+    runs: Vec<Run>,
     format: Format,
     output: Output,
-    samples: Vec<Samples>,
+    output_path: PathBuf,
+    writer: Box<dyn Write>,
+    interval: Duration,
+    /// Longest the wait between samples can grow to with `--adaptive`.
+    max_interval: Option<Duration>,
+    /// How long `--follow-name` looks for a process of the same name.
+    follow_name: Option<Duration>,
+    /// Tolerance of `--on-change`, below which a sample isn't written.
+    on_change: Option<f32>,
+    /// Change of `mem` in bytes `--on-change` needs on top of its tolerance.
+    min_mem_delta: Option<u64>,
+    duration: Option<Duration>,
+    warmup: Duration,
+    /// How long a spawned program gets to show up before sampling it.
+    startup_timeout: Duration,
+    /// Taken off every sample with `--baseline`.
+    baseline: Option<baseline::Baseline>,
+    baseline_path: Option<PathBuf>,
+    max_samples: Option<u64>,
+    /// Decimal places of the float fields with `--precision`.
+    precision: Option<u32>,
+    /// What happens once `max_samples` is reached.
+    sample_limit_action: SampleLimitAction,
+    bucket: Option<Duration>,
+    tree: bool,
+    /// Times each program is run with `--repeat`, which are next to each other in `runs`.
+    repeat: usize,
+    /// Whether the repeats are folded into one with `--average`.
+    average: bool,
+    trace_spawns: bool,
+    markers: Option<markers::Markers>,
+    /// Lines appended to `--phase-file`, each setting `phase`.
+    phase_file: Option<markers::Tail>,
+    /// What the last line of `--phase-file` set, carried by every sample after it.
+    phase: Option<String>,
+    per_core: bool,
+    cgroup: bool,
+    system_usage: bool,
+    smooth: Option<usize>,
+    fail_if_cpu_over: Option<f32>,
+    fail_if_mem_over: Option<u64>,
+    /// Growth in bytes over a run that counts as a leak, with `--detect-leak`.
+    leak_threshold: Option<u64>,
+    fail_on_leak: bool,
+    cpu_scale: CpuScale,
+    mem_unit: MemUnit,
+    summary: bool,
+    /// Bins of each histogram with `--histogram`.
+    histogram: Option<usize>,
+    full: bool,
+    legacy_json: bool,
+    /// Whether every sample repeats its run's `uuid`, rather than only the metadata having it.
+    per_row_uuid: bool,
+    /// Whether CSV and TSV output starts with a header, which it doesn't when appending to existing rows.
+    csv_header: bool,
+    watch: Option<watch::Watch>,
+    /// Fields of each sample to write with `--columns`, every one when empty.
+    columns: Vec<String>,
+    time_format: TimeFormat,
+    /// Whether only the peaks are kept, not every sample.
+    count_only: bool,
+    /// Samples between flushes of streamed output, and how many have been written since the last one.
+    flush_every: usize,
+    unflushed: usize,
+    #[cfg(feature = "push")]
+    push: Option<push::Push>,
+    /// Whether a failed push was already reported, so a down endpoint doesn't flood stderr.
+    #[cfg(feature = "push")]
+    push_failed: bool,
+    kill_on_interrupt: bool,
+    verbosity: Verbosity,
+    /// Whether to only print what would be done, with `--dry-run`.
+    dry_run: bool,
+    no_output: bool,
     crtl_c_interupt: Receiver<()>,
 }
 
 impl Peek {
-    fn new(cli: Cli) -> Result<Self> {
-        let (tx, rx) = channel();
-        ctrlc::set_handler(move || tx.send(()).unwrap())?;
+    /// Sets up sampling as `cli` asks, stopping early once something is received on `interrupt`.
+    fn new(mut cli: Cli, interrupt: Receiver<()>) -> Result<Self> {
+        if cli.output_dir.is_some() && !cli.output.writes_file() {
+            cli.output = Output::File;
+        }
 
-        let program = Program::new(&cli)?;
+        // Resolved before anything else looks at the format.
+        let unknown_extension = cli.format == Format::Auto
+            && cli
+                .path
+                .as_deref()
+                .is_some_and(|path| Format::infer(path).is_none());
+        cli.format = cli.format.resolve(cli.path.as_deref());
 
-        Ok(Self {
-            system: System::new(),
-            program,
-            output_path: cli.path.unwrap_or_else(|| {
-                let mut output = std::env::current_dir().expect("couldn't get cwd");
-                output.push(format!("peek.{}", cli.format));
-                output
-            }),
-            crtl_c_interupt: rx,
+        let mut system = System::new();
+
+        if let Some(name) = &cli.name {
+            system.refresh_processes_specifics(ProcessRefreshKind::new());
+            cli.pid = Some(pid_by_name(&system, name)?);
+        }
+
+        let runs = match (cli.pid, &cli.pattern) {
+            // Nothing to check up front, processes matching it may only start later.
+            (None, Some(pattern)) => {
+                let label = cli.label.clone().unwrap_or_else(|| pattern.to_string());
+
+                vec![Run::new(
+                    Target::Match(pattern.clone()),
+                    Vec::new(),
+                    None,
+                    label,
+                )]
+            }
+            (Some(pid), _) => {
+                if !system.refresh_process(Pid::from(pid)) {
+                    anyhow::bail!("no process with pid `{pid}` is running");
+                }
+
+                // Empty when the working directory can't be read, e.g. missing permissions.
+                let cwd = system
+                    .process(Pid::from(pid))
+                    .map(|process| process.cwd().to_path_buf())
+                    .filter(|cwd| !cwd.as_os_str().is_empty());
+
+                let name = system
+                    .process(Pid::from(pid))
+                    .map(|process| process.name().to_string())
+                    .unwrap_or_default();
+
+                let label = cli.label.clone().unwrap_or(name);
+
+                let command = system
+                    .process(Pid::from(pid))
+                    .map(|process| process.cmd().to_vec())
+                    .unwrap_or_default();
+
+                vec![Run::new(Target::Attach(pid), command, cwd, label)]
+            }
+            (None, None) => {
+                let programs = programs(&cli)?;
+
+                if programs.is_empty() {
+                    anyhow::bail!("expected a program to run or `--pid`");
+                }
+
+                let repeat = cli.repeat.unwrap_or(1);
+
+                programs
+                    .into_iter()
+                    .flat_map(|(command, name)| {
+                        (1..=repeat).map(move |i| (command.clone(), name.clone(), i))
+                    })
+                    .map(|(command, name, i)| {
+                        let program = Program::new(command, &cli)?;
+                        let cwd = Some(program.cwd.clone());
+                        // `--label` conflicts with `--cmd`, so it only ever names a single program.
+                        let label = cli.label.clone().unwrap_or(name);
+                        // Told apart unless folded back into one.
+                        let label = match repeat {
+                            1 => label,
+                            _ if cli.average => label,
+                            _ => format!("{label} #{i}"),
+                        };
+                        let command = std::iter::once(&program.command)
+                            .chain(&program.args)
+                            .cloned()
+                            .collect();
+                        Ok(Run::new(Target::Spawn(program), command, cwd, label))
+                    })
+                    .collect::<Result<_>>()?
+            }
+        };
+
+        let interval = cli
+            .interval
+            .map(Duration::from_millis)
+            .unwrap_or(System::MINIMUM_CPU_UPDATE_INTERVAL);
+
+        // CPU usage needs at least `MINIMUM_CPU_UPDATE_INTERVAL` between refreshes to be accurate, and every
+        // refresh for memory resets what it's measured against, so faster sampling keeps it apart.
+        let cpu_system = (interval < System::MINIMUM_CPU_UPDATE_INTERVAL).then(System::new);
+
+        let file_name = |stem: &str| match cli.compress.extension() {
+            Some(extension) => format!("{stem}.{}.{extension}", cli.format),
+            None => format!("{stem}.{}", cli.format),
+        };
+
+        let output_path = match (cli.path.take(), &cli.output_dir) {
+            (Some(path), _) => path,
+            // Named so runs written to the same directory don't overwrite each other.
+            (None, Some(dir)) => match &cli.label {
+                Some(label) => dir.join(file_name(&sanitize(label))),
+                None => dir.join(file_name(&runs[0].uuid.to_string())),
+            },
+            (None, None) => std::env::current_dir()
+                .expect("couldn't get cwd")
+                .join(file_name("peek")),
+        };
+
+        if cfg!(not(feature = "parquet")) && cli.format == Format::Parquet {
+            anyhow::bail!("parquet output needs `peek` to be built with the `parquet` feature");
+        }
+
+        if cfg!(not(feature = "msgpack")) && cli.format == Format::Msgpack {
+            anyhow::bail!("msgpack output needs `peek` to be built with the `msgpack` feature");
+        }
+
+        if cfg!(not(feature = "sqlite")) && cli.format == Format::Sqlite {
+            anyhow::bail!("sqlite output needs `peek` to be built with the `sqlite` feature");
+        }
+
+        cli.compress.check()?;
+
+        if cli.compress != Compression::None {
+            if !cli.output.writes_file() {
+                anyhow::bail!("`--compress` needs `--output file` or `--output both`");
+            }
+
+            if cli.format == Format::Sqlite {
+                anyhow::bail!("the sqlite format can't be compressed");
+            }
+        }
+
+        if cli.sample_limit_action == SampleLimitAction::Ring && cli.format == Format::Ndjson {
+            anyhow::bail!(
+                "`--sample-limit-action ring` doesn't work with ndjson, which is written as it's sampled"
+            );
+        }
+
+        if cli.average && cli.format == Format::Ndjson {
+            anyhow::bail!("`--average` doesn't work with ndjson, which is written as it's sampled");
+        }
+
+        if cli.watch && cli.output == Output::Stderr {
+            anyhow::bail!(
+                "`--watch` draws on stderr, which `--output stderr` is already writing to"
+            );
+        }
+
+        // Printed on stderr too, where they'd end up mixed into the data.
+        if cli.output == Output::Stderr && !cli.no_output {
+            for (given, flag) in [
+                (cli.summary, "--summary"),
+                (cli.histogram, "--histogram"),
+                (cli.detect_leak, "--detect-leak"),
+                (cli.fail_on_leak, "--fail-on-leak"),
+            ] {
+                if given {
+                    anyhow::bail!(
+                        "`{flag}` prints to stderr, which `--output stderr` is already writing to"
+                    );
+                }
+            }
+        }
+
+        if cli.bucket.is_some() && cli.format == Format::Ndjson {
+            anyhow::bail!("`--bucket` doesn't work with ndjson, which is written as it's sampled");
+        }
+
+        if cli.count_only && !matches!(cli.format, Format::Json | Format::Yaml) {
+            anyhow::bail!(
+                "`--count-only` only works with json and yaml, `{}` has nothing to show without samples",
+                cli.format
+            );
+        }
+
+        check_columns(&cli.columns, cli.time_format, cli.format)?;
+
+        if cli.flush_every.is_some() && cli.format != Format::Ndjson {
+            anyhow::bail!(
+                "`--flush-every` only works with ndjson, `{}` is written once sampling is done",
+                cli.format
+            );
+        }
+
+        if cli.append {
+            if !cli.output.writes_file() {
+                anyhow::bail!("`--append` needs `--output file` or `--output both`");
+            }
+
+            if !matches!(
+                cli.format,
+                Format::Ndjson | Format::Csv | Format::Tsv | Format::Sqlite
+            ) {
+                anyhow::bail!(
+                    "`--append` only works with the ndjson, csv, tsv and sqlite formats, appending to `{}` would make it invalid",
+                    cli.format
+                );
+            }
+        }
+
+        // Loaded before the output is created, which would truncate it for nothing if this failed.
+        let baseline = cli
+            .baseline
+            .as_deref()
+            .map(baseline::Baseline::load)
+            .transpose()?;
+
+        // The directory the output goes in when it's yet to be created, which `--output-dir` always is.
+        let missing = output_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty() && !parent.is_dir())
+            .filter(|_| cli.output.writes_file() && !cli.no_output);
+
+        if let Some(missing) = missing.filter(|_| !cli.mkdir && cli.output_dir.is_none()) {
+            anyhow::bail!(
+                "directory `{}` doesn't exist, create it or pass `--mkdir`",
+                missing.display()
+            );
+        }
+
+        if cli.dry_run {
+            for run in &runs {
+                if let Target::Spawn(program) = &run.target {
+                    program.resolve()?;
+                }
+            }
+
+            // A missing directory is only created once sampling for real.
+            if cli.output.writes_file() && missing.is_none() {
+                writable(&output_path)?;
+            }
+        }
+
+        if let Some(missing) = missing.filter(|_| !cli.dry_run) {
+            std::fs::create_dir_all(missing)
+                .with_context(|| format!("failed to create `{}`", missing.display()))?;
+        }
+
+        // Appending to existing rows, which already start with a header.
+        let mut csv_header = true;
+
+        // Opened up front so streaming formats can write each sample as it is taken.
+        let writer: Box<dyn Write> = match (cli.output, cli.format) {
+            _ if cli.no_output => Box::new(std::io::sink()),
+            // Written through its own connection, creating the file here would truncate the database.
+            (Output::File, Format::Sqlite) => Box::new(std::io::sink()),
+            (Output::Stdout | Output::Stderr | Output::Both, Format::Sqlite) => {
+                anyhow::bail!(
+                    "the sqlite format can only be written to a file, use `--output file`"
+                )
+            }
+            // Creating the file would already truncate it.
+            _ if cli.dry_run => Box::new(std::io::sink()),
+            (Output::Stdout, _) => Box::new(std::io::stdout()),
+            (Output::Stderr, _) => Box::new(std::io::stderr()),
+            (output, _) => {
+                let file = if cli.append {
+                    let file = File::options()
+                        .create(true)
+                        .append(true)
+                        .open(&output_path)
+                        .with_context(|| format!("failed to open `{}`", output_path.display()))?;
+
+                    csv_header = file.metadata()?.len() == 0;
+
+                    file
+                } else {
+                    File::create(&output_path)
+                        .with_context(|| format!("failed to create `{}`", output_path.display()))?
+                };
+
+                let file = cli.compress.writer(file)?;
+
+                match output {
+                    Output::Both => Box::new(Tee(file, std::io::stdout())),
+                    Output::File | Output::Stdout | Output::Stderr => file,
+                }
+            }
+        };
+
+        let peek = Self {
+            system,
+            cpu_system,
+            runs,
+            output_path,
+            writer,
+            crtl_c_interupt: interrupt,
             format: cli.format,
-            samples: Vec::with_capacity(1024),
             output: cli.output,
-        })
+            interval,
+            max_interval: cli
+                .adaptive
+                .then(|| Duration::from_millis(cli.max_interval.unwrap_or(5000)).max(interval)),
+            on_change: cli.on_change,
+            min_mem_delta: cli.min_mem_delta,
+            follow_name: cli.follow_name.map(Duration::from_secs),
+            duration: cli.duration.map(Duration::from_secs),
+            warmup: Duration::from_millis(cli.warmup.unwrap_or_default()),
+            startup_timeout: Duration::from_millis(cli.startup_timeout),
+            baseline,
+            baseline_path: cli.baseline.clone(),
+            max_samples: cli.max_samples,
+            sample_limit_action: cli.sample_limit_action,
+            precision: cli.precision,
+            bucket: cli.bucket.map(Duration::from_secs),
+            tree: cli.tree,
+            repeat: cli.repeat.unwrap_or(1) as usize,
+            average: cli.average,
+            trace_spawns: cli.trace_spawns,
+            markers: (cli.markers || cli.marker_file.is_some())
+                .then(|| markers::Markers::new(cli.markers, cli.marker_file.as_deref()))
+                .transpose()?,
+            phase_file: cli
+                .phase_file
+                .as_deref()
+                .map(|path| markers::Tail::open(path, "phase file"))
+                .transpose()?,
+            phase: cli.phase_file.is_some().then(String::new),
+            per_core: cli.per_core,
+            cgroup: cli.cgroup,
+            system_usage: cli.system,
+            smooth: cli.smooth.map(NonZeroUsize::get),
+            fail_if_cpu_over: cli.fail_if_cpu_over,
+            fail_if_mem_over: cli.fail_if_mem_over,
+            leak_threshold: (cli.detect_leak || cli.fail_on_leak).then_some(cli.leak_threshold),
+            fail_on_leak: cli.fail_on_leak,
+            cpu_scale: cli.cpu_scale,
+            mem_unit: cli.mem_unit.unwrap_or(MemUnit::Bytes),
+            summary: cli.summary,
+            histogram: cli.histogram.then_some(cli.bins.get()),
+            full: cli.full,
+            legacy_json: cli.legacy_json,
+            // The database joins samples to their run by it, and the legacy shape has no metadata to hold it.
+            per_row_uuid: cli.per_row_uuid || cli.format == Format::Sqlite || cli.legacy_json,
+            csv_header,
+            watch: cli.watch.then(|| watch::Watch::new(cli.mem_unit)),
+            columns: cli.columns.clone(),
+            time_format: cli.time_format,
+            // Nothing needs the samples without output, unless they're checked for a leak.
+            count_only: cli.count_only
+                || (cli.no_output && !cli.detect_leak && !cli.fail_on_leak && !cli.histogram),
+            flush_every: cli.flush_every.map_or(1, NonZeroUsize::get),
+            unflushed: 0,
+            #[cfg(feature = "push")]
+            push: cli.push.as_deref().map(push::Push::new).transpose()?,
+            #[cfg(feature = "push")]
+            push_failed: false,
+            kill_on_interrupt: !cli.no_kill,
+            verbosity: match (cli.quiet, cli.verbose) {
+                (true, _) => Verbosity::Quiet,
+                (_, true) => Verbosity::Verbose,
+                // Kept out of what's written, which would no longer parse otherwise.
+                _ if cli.output == Output::Stderr => Verbosity::Quiet,
+                _ => Verbosity::Normal,
+            },
+            dry_run: cli.dry_run,
+            no_output: cli.no_output,
+        };
+
+        if unknown_extension {
+            peek.warn(format!(
+                "couldn't tell the format from the extension of `{}`, writing json",
+                peek.output_path.display()
+            ));
+        }
+
+        if peek.cpu_system.is_some() {
+            peek.warn(format!(
+                "CPU usage is unreliable below {}ms, so it's only refreshed that often and repeats in between, \
+                 memory and disk are still sampled every {}ms",
+                System::MINIMUM_CPU_UPDATE_INTERVAL.as_millis(),
+                interval.as_millis()
+            ));
+        }
+
+        Ok(peek)
     }
 
+    /// Samples every run one after the other, stopping early when interrupted.
     fn run(&mut self) -> Result<()> {
-        let uuid = Uuid::new_v4();
+        let mut runs = std::mem::take(&mut self.runs);
+
+        for run in &mut runs {
+            let interrupted = !self.sample(run)?;
+
+            run.end_time = Some(unix_millis()?);
+
+            // Once the ring wrapped, the oldest sample is the one after the last written. Nothing is kept to
+            // put in order with `--count-only`.
+            let len = run.samples.len() as u64;
+
+            if self.sample_limit_action == SampleLimitAction::Ring
+                && self.max_samples == Some(len)
+                && run.collected > len
+            {
+                run.samples.rotate_left((run.collected % len) as usize);
+            }
+
+            if interrupted {
+                break;
+            }
+        }
+
+        self.runs = runs;
+
+        Ok(())
+    }
+
+    /// Samples a single run until it finishes, returning `false` if `peek` was interrupted.
+    fn sample(&mut self, run: &mut Run) -> Result<bool> {
+        // The process everything is sampled relative to, of which there is none with `--match`.
+        let (mut root, finished_running, mut waiter, captures, job) = match &run.target {
+            Target::Spawn(program) => {
+                let program = program.run()?;
+                (
+                    Some(program.pid),
+                    Some(program.finished_running),
+                    Some(program.waiter),
+                    program.captures,
+                    program.job,
+                )
+            }
+            Target::Attach(pid) => (Some(*pid), None, None, None, None),
+            Target::Match(_) => (None, None, None, None, None),
+        };
+
+        let pattern = match &run.target {
+            Target::Match(pattern) => Some(pattern.clone()),
+            Target::Spawn(_) | Target::Attach(_) => None,
+        };
+
+        // The status of a program that exited before it was ever seen.
+        let mut exited = None;
 
-        let program = self.program.run()?;
+        match (&pattern, root) {
+            (Some(pattern), _) => self.refresh_matching(pattern),
+            (None, Some(pid)) => {
+                let started = Instant::now();
+
+                // A program just spawned can take a moment to show up on some systems. Waiting on it stops
+                // as soon as it exits, so a fast command isn't held up.
+                while !self.refresh(Pid::from(pid)) && started.elapsed() < self.startup_timeout {
+                    let Some(finished) = &finished_running else {
+                        break;
+                    };
+
+                    if let Ok(status) = finished.recv_timeout(Duration::from_millis(10)) {
+                        exited = Some(status);
+                        break;
+                    }
+                }
+            }
+            (None, None) => {}
+        }
+
+        // Read once, limits are set before a process starts and rarely change after.
+        let mem_limit = root.and_then(procfs::mem_limit);
+
+        // Everything but a single process is refreshed whole, which is what CPU usage then has to follow.
+        let mut cpu_root = root
+            .filter(|_| pattern.is_none() && !self.tree)
+            .map(Pid::from);
+        if let Some(cpu_system) = &mut self.cpu_system {
+            refresh_cpu_usage(cpu_system, cpu_root);
+        }
+        let mut last_cpu_refresh = Instant::now();
 
-        self.system.refresh_processes();
         self.system.refresh_cpu();
         let threads = self.system.cpus().len();
 
+        self.debug(format!(
+            "sampling `{}` every {}ms, {threads} cpus detected",
+            run.label,
+            self.interval.as_millis()
+        ));
+
         let mut sample = 0;
 
+        let start = Instant::now();
+        run.start_time = Some(unix_millis()?);
+
+        // When and with what cumulative disk usage the last sample was taken, for computing rates.
+        let mut previous: Option<(Instant, u64, u64)> = None;
+
+        // The same for network traffic, which can come and go on its own.
+        let mut previous_net: Option<(Instant, procfs::NetDev)> = None;
+
+        // And for the energy used by the CPU packages.
+        let mut previous_energy: Option<(Instant, Vec<procfs::Energy>)> = None;
+
+        // And for the CPU time of a job, which unlike `sysinfo` only counts the total.
+        let mut previous_cpu_time: Option<(Instant, Duration)> = None;
+
+        // The `cpu` of the last `--smooth` samples, oldest first.
+        let mut recent_cpu = VecDeque::with_capacity(self.smooth.unwrap_or_default());
+
+        // How long to wait until the next sample, which only changes with `--adaptive`.
+        let mut wait = self.interval;
+        let mut last_recorded: Option<(f32, u64)> = None;
+
+        let mut spawns = self.trace_spawns.then(spawns::Tracker::default);
+
+        // Name of the root process, for finding it again with `--follow-name`.
+        let mut last_name: Option<String> = None;
+
+        // What the last sample written had, which `--on-change` compares every one after against.
+        let mut last_kept: Option<(f32, u64)> = None;
+
         loop {
-            if program.finished_running.try_recv().is_ok()
-                || self.crtl_c_interupt.try_recv().is_ok()
+            let status = exited.take().or_else(|| {
+                finished_running
+                    .as_ref()
+                    .and_then(|finished| finished.try_recv().ok())
+            });
+
+            if let Some(status) = status {
+                run.exit_code = status.code();
+                run.signal = signal(status);
+                break;
+            }
+
+            if self.crtl_c_interupt.try_recv().is_ok() {
+                // Only kill programs `peek` spawned itself, never an attached process.
+                if let (Some(finished), Some(waiter)) = (&finished_running, waiter.take()) {
+                    if self.kill_on_interrupt {
+                        run.exit_code = root
+                            .and_then(|pid| self.terminate(Pid::from(pid), finished))
+                            .and_then(|status| status.code());
+
+                        // The program has been reaped by now, so this doesn't block.
+                        let _ = waiter.join();
+                    }
+                }
+
+                run.capture(captures);
+
+                return Ok(false);
+            }
+
+            if self
+                .duration
+                .is_some_and(|duration| start.elapsed() >= self.warmup + duration)
             {
                 break;
             }
 
-            self.system.refresh_processes();
+            // Refreshed before the process borrows `self.system` for the rest of the iteration.
+            let cpu_cores = self.per_core.then(|| {
+                self.system.refresh_cpu();
+                self.system
+                    .cpus()
+                    .iter()
+                    .map(|cpu| cpu.cpu_usage())
+                    .collect()
+            });
 
-            let process = self
-                .system
-                .process(sysinfo::Pid::from(program.pid))
-                .with_context(|| "no such process is running")?;
+            let system = self.system_usage.then(|| {
+                self.system.refresh_cpu();
+                self.system.refresh_memory();
+                let memory = |bytes| Memory {
+                    bytes,
+                    unit: self.mem_unit,
+                };
+                let load = cfg!(unix).then(|| self.system.load_average());
 
-            self.samples.push(Samples {
-                uuid,
-                sample,
-                pid: program.pid,
-                name: process.name().to_string(),
-                cpu: process.cpu_usage() / threads as f32,
-                mem: process.memory(),
-                virt_mem: process.virtual_memory(),
-                disk_read: process.disk_usage().total_read_bytes,
-                disk_write: process.disk_usage().total_written_bytes,
+                SystemUsage {
+                    system_cpu: self.system.global_cpu_info().cpu_usage(),
+                    system_mem_total: memory(self.system.total_memory()),
+                    system_mem_used: memory(self.system.used_memory()),
+                    system_mem_available: memory(self.system.available_memory()),
+                    load_1: load.as_ref().map(|load| load.one),
+                    load_5: load.as_ref().map(|load| load.five),
+                    load_15: load.as_ref().map(|load| load.fifteen),
+                }
             });
 
-            sample += 1;
+            if let Some(cpu_system) = self
+                .cpu_system
+                .as_mut()
+                .filter(|_| last_cpu_refresh.elapsed() >= System::MINIMUM_CPU_UPDATE_INTERVAL)
+            {
+                refresh_cpu_usage(cpu_system, cpu_root);
+                last_cpu_refresh = Instant::now();
+            }
 
-            // 200ms
-            std::thread::sleep(System::MINIMUM_CPU_UPDATE_INTERVAL);
-        }
-        Ok(())
-    }
+            // Seconds the root process has been running, and when it started in unix epoch seconds.
+            let (processes, name, uptime) = match (&pattern, root) {
+                (Some(pattern), _) => {
+                    self.refresh_matching(pattern);
 
-    fn output(self) -> Result<()> {
-        fn to_json(data: Vec<Samples>) -> String {
-            serde_json::json!(data).to_string()
-        }
+                    let processes: Vec<&Process> = self
+                        .system
+                        .processes()
+                        .values()
+                        .filter(|process| pattern.is_match(process.name()))
+                        .collect();
 
-        fn to_csv(_data: Vec<Samples>) -> String {
-            todo!()
-        }
+                    (processes, pattern.to_string(), None)
+                }
+                (None, Some(pid)) => {
+                    let process = self
+                        .refresh(Pid::from(pid))
+                        .then(|| self.system.process(Pid::from(pid)))
+                        .flatten();
 
-        let data = match self.format {
-            Format::Csv => to_csv(self.samples),
-            Format::Json => to_json(self.samples),
-        };
+                    let Some(process) = process else {
+                        // Only an attached process can be replaced, a spawned program has an exit code to wait on.
+                        let followed = self
+                            .follow_name
+                            .filter(|_| finished_running.is_none())
+                            .zip(last_name.as_deref())
+                            .and_then(|(timeout, name)| self.find_by_name(name, timeout));
 
-        match self.output {
-            Output::File => {
-                let mut file = File::create(self.output_path)?;
+                        if let Some(next) = followed {
+                            self.warn(format!(
+                                "`{}` (pid {pid}) is gone, following pid {next} of the same name",
+                                last_name.as_deref().unwrap_or_default()
+                            ));
+                            root = Some(next);
+                            cpu_root = cpu_root.map(|_| Pid::from(next));
+                            run.cmdline.clear();
+                            continue;
+                        }
 
-                file.write_all(data.as_bytes())?;
-            }
-            Output::Stdout => {
-                println!("{data}");
-            }
-        }
+                        // The process exited since the check above, the samples taken so far are kept. For a
+                        // spawned program its status is sent as soon as it has been reaped, which may still be
+                        // in flight.
+                        if let Some(Ok(status)) = finished_running
+                            .as_ref()
+                            .map(|finished| finished.recv_timeout(Duration::from_secs(1)))
+                        {
+                            run.exit_code = status.code();
+                            run.signal = signal(status);
+                        }
 
-        Ok(())
-    }
-}
+                        break;
+                    };
 
-#[derive(Debug)]
-struct Program {
-    command: String,
-    args: Vec<String>,
-}
+                    let processes = if self.tree {
+                        process_tree(&self.system, Pid::from(pid))
+                    } else {
+                        vec![process]
+                    };
 
-impl Program {
-    pub fn new(cli: &Cli) -> Result<Self> {
-        let command: Vec<String> = cli
-            .program
-            .split_whitespace()
-            .map(|param| param.to_owned())
-            .collect();
+                    let uptime = (process.run_time(), process.start_time());
 
-        Ok(Self {
-            command: command[0].clone(),
-            args: command[1..].to_vec(),
-        })
-    }
+                    last_name = Some(process.name().to_string());
 
-    pub fn run(&self) -> Result<RunningProgram> {
-        let (status_tx, status_rx) = channel();
-        let (pid_tx, pid_rx) = channel();
+                    if run.cmdline.is_empty() {
+                        run.cmdline = process.cmd().to_vec();
+                    }
 
-        let command = self.command.clone();
-        let args = self.args.clone();
+                    (processes, process.name().to_string(), Some(uptime))
+                }
+                (None, None) => unreachable!("only `--match` samples without a root process"),
+            };
 
-        std::thread::spawn(move || {
-            let command = command;
-            let args = args;
+            if let (Some(tracker), Some(root)) = (&mut spawns, root) {
+                let events = tracker.update(&processes, root, unix_millis()?);
 
-            if let Ok(mut child) = Command::new(&command).args(&args).spawn() {
-                pid_tx.send(child.id()).unwrap();
+                for event in &events {
+                    self.debug(format!(
+                        "{:?} of `{}` (pid {})",
+                        event.event, event.name, event.pid
+                    ));
+                }
 
-                status_tx
-                    .send(child.wait().unwrap())
-                    .expect("failed to send finsihed programs status back to peep");
-            } else {
-                let cwd = std::env::current_dir().unwrap();
-                let command = cwd.join(&command);
+                run.events.extend(events);
+            }
 
-                let mut child = Command::new(command).args(&args).spawn().unwrap();
+            if let Some(markers) = &mut self.markers {
+                let markers = markers.poll(sample)?;
 
-                pid_tx.send(child.id()).unwrap();
+                for marker in &markers {
+                    self.debug(format!(
+                        "marker `{}` before sample {}",
+                        marker.label.as_deref().unwrap_or("SIGUSR1"),
+                        marker.sample
+                    ));
+                }
 
-                status_tx
-                    .send(child.wait().unwrap())
-                    .expect("failed to send finsihed programs status back to peep");
+                run.markers.extend(markers);
             }
-        });
 
-        let pid = pid_rx.recv().unwrap() as usize;
+            // Only the last line counts when several came in since the previous sample.
+            if let Some(file) = &mut self.phase_file {
+                if let Some(phase) = file.lines()?.pop() {
+                    self.debug(format!("phase `{phase}` from sample {sample}"));
+                    self.phase = Some(phase);
+                }
+            }
 
-        Ok(RunningProgram {
-            pid,
-            finished_running: status_rx,
-        })
-    }
-}
+            // Refreshed like any other sample so the first kept one has a baseline, but not recorded. Neither
+            // are the times nothing matches `--match`, while waiting on processes to start.
+            if start.elapsed() < self.warmup || processes.is_empty() {
+                std::thread::sleep(self.interval);
+                continue;
+            }
 
-struct RunningProgram {
-    pid: usize,
-    finished_running: Receiver<ExitStatus>,
-}
+            let timestamp = unix_millis()?;
+            let now = Instant::now();
 
-// TODO: Time the samples and store in field.
-#[derive(Debug, serde::Serialize)]
-struct Samples {
-    uuid: Uuid,
-    sample: u64,
-    pid: usize,
-    name: String,
-    cpu: f32,
-    mem: u64,
-    virt_mem: u64,
-    disk_read: u64,
-    disk_write: u64,
-}
+            // Counts every process the program started, where walking the tree wouldn't.
+            let accounting = job.as_ref().and_then(job::Job::accounting);
 
-#[derive(Parser)]
-#[command(author, version, about, long_about = None)]
-struct Cli {
-    program: String,
-    // #[arg(long, short)]
-    // pid: Option<usize>,
-    path: Option<PathBuf>,
-    #[arg(long, short, default_value = "stdout")]
-    output: Output,
-    #[arg(value_enum, long, short, default_value = "json")]
-    format: Format,
-}
+            if let Some((_, started)) = uptime {
+                run.process_start_time = Some(started * 1000);
+            }
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
-enum Output {
-    File,
-    Stdout,
-}
+            let disk_read: u64 = accounting.map_or_else(
+                || {
+                    processes
+                        .iter()
+                        .map(|p| p.disk_usage().total_read_bytes)
+                        .sum()
+                },
+                |accounting| accounting.read_bytes,
+            );
+            let disk_write: u64 = accounting.map_or_else(
+                || {
+                    processes
+                        .iter()
+                        .map(|p| p.disk_usage().total_written_bytes)
+                        .sum()
+                },
+                |accounting| accounting.written_bytes,
+            );
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
-enum Format {
-    Csv,
-    Json,
-}
+            let delta_ms = previous.map_or(0, |(then, _, _)| {
+                now.duration_since(then).as_millis() as u64
+            });
 
-impl Display for Format {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let str = match self {
-            Self::Csv => "csv",
-            Self::Json => "json",
-        };
+            let (disk_read_rate, disk_write_rate) = match previous {
+                Some((then, read, written)) => {
+                    let elapsed = now.duration_since(then).as_secs_f64();
+                    (
+                        disk_read.saturating_sub(read) as f64 / elapsed,
+                        disk_write.saturating_sub(written) as f64 / elapsed,
+                    )
+                }
+                None => (0.0, 0.0),
+            };
 
-        write!(f, "{}", str)
+            previous = Some((now, disk_read, disk_write));
+
+            let power_watts = procfs::package_energy().map(|energy| {
+                let watts = match &previous_energy {
+                    Some((then, before)) if before.len() == energy.len() => {
+                        let microjoules: u64 = energy
+                            .iter()
+                            .zip(before)
+                            .map(|(now, before)| now.since(*before))
+                            .sum();
+
+                        microjoules as f64 / 1_000_000.0 / now.duration_since(*then).as_secs_f64()
+                    }
+                    _ => 0.0,
+                };
+
+                previous_energy = Some((now, energy));
+
+                watts
+            });
+
+            let network = root.and_then(procfs::net_dev).map(|net| {
+                let (net_rx_rate, net_tx_rate) = match previous_net {
+                    Some((then, before)) => {
+                        let elapsed = now.duration_since(then).as_secs_f64();
+                        (
+                            net.rx.saturating_sub(before.rx) as f64 / elapsed,
+                            net.tx.saturating_sub(before.tx) as f64 / elapsed,
+                        )
+                    }
+                    None => (0.0, 0.0),
+                };
+
+                previous_net = Some((now, net));
+
+                Network {
+                    net_rx: net.rx,
+                    net_tx: net.tx,
+                    net_rx_rate,
+                    net_tx_rate,
+                }
+            });
+
+            let cpu: f32 = match accounting {
+                Some(accounting) => {
+                    let cpu = match previous_cpu_time {
+                        Some((then, cpu_time)) => {
+                            (accounting.cpu_time.saturating_sub(cpu_time).as_secs_f64()
+                                / now.duration_since(then).as_secs_f64()
+                                * 100.0) as f32
+                        }
+                        None => 0.0,
+                    };
+
+                    previous_cpu_time = Some((now, accounting.cpu_time));
+
+                    cpu
+                }
+                // Between its refreshes this is the last CPU usage seen, while everything else is current.
+                None => match &self.cpu_system {
+                    Some(cpu_system) => processes
+                        .iter()
+                        .filter_map(|p| cpu_system.process(p.pid()))
+                        .map(Process::cpu_usage)
+                        .sum(),
+                    None => processes.iter().map(|p| p.cpu_usage()).sum(),
+                },
+            };
+            let cpu = match self.cpu_scale {
+                CpuScale::Core => cpu,
+                CpuScale::System => cpu / threads as f32,
+            };
+
+            let cpu_smoothed = self.smooth.map(|window| {
+                if recent_cpu.len() == window {
+                    recent_cpu.pop_front();
+                }
+
+                recent_cpu.push_back(cpu);
+
+                recent_cpu.iter().sum::<f32>() / recent_cpu.len() as f32
+            });
+
+            let mem = processes.iter().map(|p| p.memory()).sum();
+            let virt_mem = processes.iter().map(|p| p.virtual_memory()).sum();
+
+            let data = Samples {
+                uuid: self.per_row_uuid.then_some(run.uuid),
+                label: run.label.clone(),
+                phase: self.phase.clone(),
+                sample,
+                timestamp,
+                delta_ms,
+                pid: root,
+                name,
+                uptime_secs: uptime.map(|(secs, _)| secs),
+                process_count: accounting
+                    .map_or(processes.len(), |accounting| accounting.active_processes),
+                cpu,
+                cpu_smoothed,
+                mem: Memory {
+                    bytes: mem,
+                    unit: self.mem_unit,
+                },
+                virt_mem: Memory {
+                    bytes: virt_mem,
+                    unit: self.mem_unit,
+                },
+                swap: sum_available(processes.iter().map(|p| procfs::swap(usize::from(p.pid()))))
+                    .map(|bytes| Memory {
+                        bytes,
+                        unit: self.mem_unit,
+                    }),
+                threads: sum_available(
+                    processes
+                        .iter()
+                        .map(|p| procfs::threads(usize::from(p.pid()))),
+                ),
+                open_files: sum_available(
+                    processes
+                        .iter()
+                        .map(|p| procfs::open_files(usize::from(p.pid()))),
+                ),
+                ctx_switches: sum_available(
+                    processes
+                        .iter()
+                        .map(|p| procfs::ctx_switches(usize::from(p.pid()))),
+                ),
+                page_faults: sum_available(
+                    processes
+                        .iter()
+                        .map(|p| procfs::page_faults(usize::from(p.pid()))),
+                ),
+                priority: root.and_then(procfs::priority),
+                cgroup: root
+                    .filter(|_| self.cgroup)
+                    .and_then(procfs::cgroup_memory)
+                    .map(|memory| Cgroup {
+                        cgroup_mem: Memory {
+                            bytes: memory.current,
+                            unit: self.mem_unit,
+                        },
+                        cgroup_mem_max: memory.max.map(|bytes| Memory {
+                            bytes,
+                            unit: self.mem_unit,
+                        }),
+                    }),
+                limit: mem_limit.map(|limit| MemLimit::new(limit, mem, virt_mem, self.mem_unit)),
+                disk_read,
+                disk_write,
+                disk_read_rate,
+                disk_write_rate,
+                network,
+                power_watts,
+                system,
+                spread: None,
+                cpu_cores,
+            };
+
+            let recorded = (data.cpu, data.mem.bytes);
+
+            let unchanged = self.on_change.is_some_and(|tolerance| {
+                last_kept
+                    .is_some_and(|last| unchanged(last, recorded, tolerance, self.min_mem_delta))
+            });
+
+            if !unchanged {
+                self.record(run, data)?;
+                last_kept = Some(recorded);
+            }
+
+            sample += 1;
+
+            if let Some(max_interval) = self.max_interval {
+                wait = adapt(wait, last_recorded, recorded, self.interval, max_interval);
+            }
+
+            last_recorded = Some(recorded);
+
+            // Counts what was written, which `--on-change` and `--min-mem-delta` leave out of some samples taken.
+            if self.max_samples.is_some_and(|max| run.collected >= max) {
+                match self.sample_limit_action {
+                    SampleLimitAction::KeepRunning => {
+                        self.warn(format!(
+                            "reached the maximum of {} samples, output is truncated",
+                            run.collected
+                        ));
+                        break;
+                    }
+                    SampleLimitAction::Stop => {
+                        self.warn(format!(
+                            "reached the maximum of {} samples, stopping the program",
+                            run.collected
+                        ));
+
+                        // Like an interrupt, an attached process is left alone.
+                        if let (Some(finished), Some(waiter)) = (&finished_running, waiter.take()) {
+                            run.exit_code = root
+                                .and_then(|pid| self.terminate(Pid::from(pid), finished))
+                                .and_then(|status| status.code());
+
+                            let _ = waiter.join();
+                        }
+
+                        break;
+                    }
+                    // Kept to the limit by `record`.
+                    SampleLimitAction::Ring => {}
+                }
+            }
+
+            std::thread::sleep(wait);
+        }
+
+        run.capture(captures);
+
+        Ok(true)
+    }
+
+    /// Looks for a process named `name` to carry on with once the one sampled is gone, for `--follow-name`,
+    /// trying again with a growing wait until `timeout` is up. Of several with that name, the one started last
+    /// is taken, as the likeliest to be a restart.
+    fn find_by_name(&mut self, name: &str, timeout: Duration) -> Option<usize> {
+        let start = Instant::now();
+        let mut wait = self.interval;
+
+        loop {
+            self.system
+                .refresh_processes_specifics(ProcessRefreshKind::new());
+
+            let found = self
+                .system
+                .processes()
+                .values()
+                .filter(|process| process.name() == name)
+                .max_by_key(|process| process.start_time())
+                .map(|process| usize::from(process.pid()));
+
+            if found.is_some() || start.elapsed() >= timeout {
+                return found;
+            }
+
+            std::thread::sleep(wait.min(timeout.saturating_sub(start.elapsed())));
+            wait = (wait * 2).min(Duration::from_secs(1));
+        }
+    }
+
+    /// Refreshes every process to find those matching `pattern`, along with their disk usage.
+    fn refresh_matching(&mut self, pattern: &Regex) {
+        self.system
+            .refresh_processes_specifics(ProcessRefreshKind::new().with_cpu());
+
+        let matching: Vec<Pid> = self
+            .system
+            .processes()
+            .values()
+            .filter(|process| pattern.is_match(process.name()))
+            .map(|process| process.pid())
+            .collect();
+
+        for pid in matching {
+            self.system
+                .refresh_process_specifics(pid, ProcessRefreshKind::new().with_disk_usage());
+        }
+    }
+
+    /// Prints a warning to stderr, unless `--quiet` is given.
+    fn warn(&self, message: impl Display) {
+        if self.verbosity >= Verbosity::Normal {
+            eprintln!("warning: {message}");
+        }
+    }
+
+    /// Prints diagnostics about `peek` itself to stderr, only with `--verbose`.
+    fn debug(&self, message: impl Display) {
+        if self.verbosity >= Verbosity::Verbose {
+            eprintln!("debug: {message}");
+        }
+    }
+
+    /// Prints what would be sampled and where it would be written, for `--dry-run`.
+    fn plan(&self) -> Result<()> {
+        let mut stdout = std::io::stdout().lock();
+
+        for run in &self.runs {
+            writeln!(stdout, "label:    {}", run.label)?;
+
+            match &run.target {
+                Target::Spawn(program) => {
+                    writeln!(stdout, "command:  {}", program.resolve()?.display())?;
+                    writeln!(stdout, "args:     {}", shell_words::join(&program.args))?;
+                    writeln!(stdout, "cwd:      {}", program.cwd.display())?;
+
+                    if program.clear_env {
+                        writeln!(stdout, "env:      (cleared)")?;
+                    }
+
+                    for (key, value) in &program.env {
+                        writeln!(stdout, "env:      {key}={value}")?;
+                    }
+                }
+                Target::Attach(pid) => writeln!(stdout, "pid:      {pid}")?,
+                Target::Match(pattern) => writeln!(stdout, "match:    {pattern}")?,
+            }
+
+            writeln!(stdout)?;
+        }
+
+        match self.max_interval {
+            Some(max) => writeln!(
+                stdout,
+                "interval: {}ms to {}ms, adaptive",
+                self.interval.as_millis(),
+                max.as_millis()
+            )?,
+            None => writeln!(stdout, "interval: {}ms", self.interval.as_millis())?,
+        }
+        writeln!(stdout, "format:   {}", self.format)?;
+
+        match self.output {
+            Output::File => writeln!(stdout, "output:   {}", self.output_path.display())?,
+            Output::Stdout => writeln!(stdout, "output:   stdout")?,
+            Output::Stderr => writeln!(stdout, "output:   stderr")?,
+            Output::Both => writeln!(
+                stdout,
+                "output:   {} and stdout",
+                self.output_path.display()
+            )?,
+        }
+
+        Ok(())
+    }
+
+    /// Asks the spawned program to stop, killing it outright if it doesn't within a few seconds, and returns
+    /// its status once it has been reaped.
+    fn terminate(&self, pid: Pid, finished: &Receiver<ExitStatus>) -> Option<ExitStatus> {
+        // Already exited, with its status on the way.
+        let Some(process) = self.system.process(pid) else {
+            return finished.recv_timeout(Duration::from_secs(1)).ok();
+        };
+
+        // Windows has no `SIGTERM`, so it goes straight to the hard kill there.
+        if process.kill_with(Signal::Term).is_some() {
+            if let Ok(status) = finished.recv_timeout(Duration::from_secs(5)) {
+                return Some(status);
+            }
+        }
+
+        process.kill();
+
+        // Returns right away if waiting failed, as the sender is dropped along with the thread.
+        finished.recv().ok()
+    }
+
+    /// Refreshes only the processes being sampled instead of every process on the system, returning
+    /// whether `root` is still running.
+    fn refresh(&mut self, root: Pid) -> bool {
+        if !self.tree {
+            return self.system.refresh_process(root);
+        }
+
+        // Discovers new processes and their parents. CPU usage has to be computed here rather than for just
+        // the tree below, as every refresh resets the CPU time it's measured against.
+        self.system
+            .refresh_processes_specifics(ProcessRefreshKind::new().with_cpu());
+
+        let tree: Vec<Pid> = process_tree(&self.system, root)
+            .iter()
+            .map(|process| process.pid())
+            .collect();
+
+        // Disk usage is the costly part, so it's only read for the tree.
+        for pid in tree {
+            self.system
+                .refresh_process_specifics(pid, ProcessRefreshKind::new().with_disk_usage());
+        }
+
+        self.system.process(root).is_some()
+    }
+
+    /// Streams the sample straight to the output if the format allows it, otherwise buffers it for `output`.
+    fn record(&mut self, run: &mut Run, mut sample: Samples) -> Result<()> {
+        sample.round(self.precision);
+
+        // Of what the program actually used, so a budget can't be passed by subtracting a `--baseline`.
+        run.peaks.update(&sample);
+        run.collected += 1;
+
+        if run.cpu_over.is_none()
+            && self
+                .fail_if_cpu_over
+                .is_some_and(|limit| sample.cpu > limit)
+        {
+            run.cpu_over = Some((sample.sample, sample.cpu));
+        }
+
+        if run.mem_over.is_none()
+            && self
+                .fail_if_mem_over
+                .is_some_and(|limit| sample.mem.bytes > limit)
+        {
+            run.mem_over = Some((sample.sample, sample.mem.bytes));
+        }
+
+        if let Some(baseline) = &self.baseline {
+            baseline.subtract(&mut sample);
+            sample.round(self.precision);
+        }
+
+        self.debug(format!(
+            "sample {}: cpu {:.2}%, mem {} bytes over {} processes",
+            sample.sample, sample.cpu, sample.mem.bytes, sample.process_count
+        ));
+
+        if let Some(watch) = &mut self.watch {
+            watch.draw(&sample)?;
+        }
+
+        #[cfg(feature = "push")]
+        if let Some(push) = &self.push {
+            if let Err(err) = push.send(&sample) {
+                if !self.push_failed {
+                    self.warn(format!(
+                        "failed to push sample, continuing without it: {err:#}"
+                    ));
+                    self.push_failed = true;
+                }
+            }
+        }
+
+        match self.format {
+            Format::Ndjson => {
+                let start = run.start_time.unwrap_or_default();
+                let row = select(&sample, &self.columns, self.time_format, start)?;
+                serde_json::to_writer(&mut self.writer, &row)?;
+                self.writer.write_all(b"\n")?;
+
+                self.unflushed += 1;
+                if self.unflushed >= self.flush_every {
+                    self.writer.flush()?;
+                    self.unflushed = 0;
+                }
+
+                // The summary and histograms can only be computed once every sample is known.
+                if self.summary || self.histogram.is_some() {
+                    run.samples.push(sample);
+                }
+            }
+            Format::Csv
+            | Format::Tsv
+            | Format::Json
+            | Format::Yaml
+            | Format::Html
+            | Format::Markdown
+            | Format::Parquet
+            | Format::Msgpack
+            | Format::Prometheus
+            | Format::Sqlite => {
+                // Only the peaks are kept with `--count-only`, which are already up to date.
+                if self.count_only {
+                    return Ok(());
+                }
+
+                // Overwrites the oldest sample once full, put back in order when sampling stops.
+                match self
+                    .max_samples
+                    .filter(|_| self.sample_limit_action == SampleLimitAction::Ring)
+                {
+                    Some(max) if run.samples.len() as u64 >= max => {
+                        run.samples[((run.collected - 1) % max) as usize] = sample;
+                    }
+                    _ => run.samples.push(sample),
+                }
+            }
+            Format::Auto => unreachable!("resolved in `Peek::new`"),
+        }
+
+        Ok(())
+    }
+
+    /// The first failing exit code of the runs, so a later success can't hide an earlier failure.
+    fn exit_code(&self) -> Option<i32> {
+        // Like a shell reports a program killed by a signal, so a crash is never taken for success.
+        let codes: Vec<i32> = self
+            .runs
+            .iter()
+            .filter_map(|run| run.exit_code.or(run.signal.map(|signal| 128 + signal)))
+            .collect();
+
+        codes
+            .iter()
+            .find(|code| **code != 0)
+            .or(codes.last())
+            .copied()
+    }
+
+    /// Prints how many samples every run collected over how long, unless `--quiet`, as a quick sign that
+    /// sampling worked and at what resolution.
+    fn print_collected(&self) {
+        if self.verbosity < Verbosity::Normal {
+            return;
+        }
+
+        for run in &self.runs {
+            let elapsed = run
+                .end_time
+                .zip(run.start_time)
+                .map_or(0, |(end, start)| end.saturating_sub(start));
+
+            let label = if self.runs.len() > 1 {
+                format!("`{}`: ", run.label)
+            } else {
+                String::new()
+            };
+
+            let average = match run.collected {
+                0 => String::new(),
+                collected => format!(" (avg interval {}ms)", elapsed / collected),
+            };
+
+            eprintln!(
+                "{label}collected {} samples over {:.1}s{average}",
+                run.collected,
+                elapsed as f64 / 1000.0
+            );
+        }
+    }
+
+    /// Prints whether `mem` trends upwards for every run with `--detect-leak`, returning whether any run
+    /// leaked with `--fail-on-leak`.
+    fn detect_leaks(&self) -> bool {
+        let Some(threshold) = self.leak_threshold else {
+            return false;
+        };
+
+        let mut leaked = false;
+
+        for run in &self.runs {
+            let Some(trend) = leak::trend(&run.samples) else {
+                eprintln!(
+                    "leak check `{}`: too few samples, at least {} are needed",
+                    run.label,
+                    leak::MIN_SAMPLES
+                );
+                continue;
+            };
+
+            let is_leak = trend.is_leak(threshold);
+            leaked |= is_leak && self.fail_on_leak;
+
+            eprintln!(
+                "leak check `{}`: mem {:+.2} {}/s over {:.1}s, r\u{b2} {:.2}, {}",
+                run.label,
+                trend.slope / self.mem_unit.divisor() as f64,
+                self.mem_unit.suffix(),
+                trend.duration,
+                trend.r_squared,
+                if is_leak { "probable leak" } else { "no leak" }
+            );
+        }
+
+        leaked
+    }
+
+    /// Describes every time a run went over `--fail-if-cpu-over` or `--fail-if-mem-over`, to report as errors.
+    fn exceeded_limits(&self) -> Vec<String> {
+        let mut exceeded = Vec::new();
+
+        for run in &self.runs {
+            if let (Some((sample, cpu)), Some(limit)) = (run.cpu_over, self.fail_if_cpu_over) {
+                exceeded.push(format!(
+                    "`{}` used {cpu:.2}% cpu at sample {sample}, over the limit of {limit}%",
+                    run.label
+                ));
+            }
+
+            if let (Some((sample, mem)), Some(limit)) = (run.mem_over, self.fail_if_mem_over) {
+                exceeded.push(format!(
+                    "`{}` used {mem} bytes of memory at sample {sample}, over the limit of {limit} bytes",
+                    run.label
+                ));
+            }
+        }
+
+        exceeded
+    }
+
+    /// Folds the runs of `--average` together and prints `--summary` and `--histogram` to stderr, whether
+    /// or not anything is written after.
+    fn report(&mut self) {
+        // Needs each repeat on its own, so it comes before `--average` folds them together.
+        if self.summary && self.repeat > 1 {
+            for group in self.runs.chunks(self.repeat) {
+                eprintln!(
+                    "{} runs of `{}`",
+                    group.len(),
+                    shell_words::join(&group[0].command)
+                );
+
+                let samples: Vec<&[Samples]> = group.iter().map(|run| &run.samples[..]).collect();
+                summary::print_variation(&samples, self.mem_unit);
+                eprintln!();
+            }
+        }
+
+        if self.average {
+            let runs = std::mem::take(&mut self.runs);
+            self.runs = repeat::average(runs, self.repeat, self.precision);
+        }
+
+        // Printed before `--bucket` rolls them up, so it's over every sample taken.
+        if self.summary {
+            for (i, run) in self.runs.iter().enumerate() {
+                if self.runs.len() > 1 {
+                    if i > 0 {
+                        eprintln!();
+                    }
+
+                    eprintln!("{}", run.label);
+                }
+
+                summary::print(&run.samples, &run.peaks, self.mem_unit);
+            }
+        }
+
+        // Also over every sample taken, binned before `--bucket` would average the extremes away.
+        if let Some(bins) = self.histogram {
+            for run in &mut self.runs {
+                run.histogram = Some(Histograms::new(&run.samples, bins));
+            }
+        }
+
+        for (i, run) in self.runs.iter().enumerate() {
+            let Some(histogram) = &run.histogram else {
+                continue;
+            };
+
+            if self.summary || i > 0 {
+                eprintln!();
+            }
+
+            if self.runs.len() > 1 {
+                eprintln!("{}", run.label);
+            }
+
+            histogram.print(self.mem_unit);
+        }
+    }
+
+    fn output(mut self) -> Result<()> {
+        if let Some(bucket) = self.bucket {
+            for run in &mut self.runs {
+                run.samples = bucket::roll_up(&run.samples, bucket);
+            }
+        }
+
+        // For `total_mem`, nothing else needed it refreshed.
+        self.system.refresh_memory();
+
+        let metadata: Vec<Metadata> = self
+            .runs
+            .iter_mut()
+            .map(|run| Metadata {
+                uuid: run.uuid,
+                label: run.label.clone(),
+                command: run.command.first().cloned(),
+                args: run.command.iter().skip(1).cloned().collect(),
+                cmdline: std::mem::take(&mut run.cmdline),
+                start_time: run.start_time,
+                end_time: run.end_time,
+                process_start_time: run.process_start_time,
+                host: self.system.host_name(),
+                os: self.system.long_os_version(),
+                kernel: self.system.kernel_version(),
+                cpu_model: self
+                    .system
+                    .cpus()
+                    .first()
+                    .map(|cpu| cpu.brand().trim().to_owned())
+                    .filter(|brand| !brand.is_empty()),
+                total_mem: Memory {
+                    bytes: self.system.total_memory(),
+                    unit: self.mem_unit,
+                },
+                cores: self.system.cpus().len(),
+                interval_ms: self.interval.as_millis() as u64,
+                max_interval_ms: self
+                    .max_interval
+                    .map(|interval| interval.as_millis() as u64),
+                cpu_scale: self.cpu_scale,
+                mem_unit: self.mem_unit,
+                exit_code: run.exit_code,
+                signal: run.signal,
+                cwd: run.cwd.clone(),
+                peak_cpu: run.peaks.cpu,
+                peak_mem: Memory {
+                    bytes: run.peaks.mem,
+                    unit: self.mem_unit,
+                },
+                peak_virt_mem: Memory {
+                    bytes: run.peaks.virt_mem,
+                    unit: self.mem_unit,
+                },
+                disk_read: run.peaks.disk_read,
+                disk_write: run.peaks.disk_write,
+                stdout: run.stdout.take(),
+                stderr: run.stderr.take(),
+                events: std::mem::take(&mut run.events),
+                markers: std::mem::take(&mut run.markers),
+                baseline: self.baseline_path.clone(),
+                histogram: run.histogram.take(),
+            })
+            .collect();
+
+        let reports: Vec<Report> = self
+            .runs
+            .iter()
+            .zip(&metadata)
+            .map(|(run, metadata)| Report {
+                metadata,
+                samples: &run.samples,
+            })
+            .collect();
+
+        // Already written out sample by sample.
+        if self.format != Format::Ndjson {
+            let rendering = Rendering {
+                format: self.format,
+                mem_unit: self.mem_unit,
+                full: self.full,
+                legacy_json: self.legacy_json,
+                csv_header: self.csv_header,
+                columns: &self.columns,
+                time_format: self.time_format,
+                #[cfg(feature = "sqlite")]
+                output_path: &self.output_path,
+            };
+
+            render(&mut self.writer, &reports, &rendering)?;
+        }
+
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// How `render` writes out runs, from sampling them or from `peek replay`.
+struct Rendering<'a> {
+    format: Format,
+    mem_unit: MemUnit,
+    full: bool,
+    legacy_json: bool,
+    csv_header: bool,
+    /// Fields of the samples to write, every one when empty.
+    columns: &'a [String],
+    time_format: TimeFormat,
+    /// Where sqlite writes to, through its own connection rather than `writer`.
+    #[cfg(feature = "sqlite")]
+    output_path: &'a Path,
+}
+
+/// Writes `reports` to `writer` in the chosen format.
+fn render(writer: &mut dyn Write, reports: &[Report], rendering: &Rendering) -> Result<()> {
+    /// A report with only the `--columns` of each sample, and their `--time-format`.
+    #[derive(serde::Serialize)]
+    struct Selected<'a> {
+        metadata: &'a Metadata,
+        samples: Vec<Map<String, Value>>,
+    }
+
+    fn to_json(reports: &[impl serde::Serialize]) -> Result<String> {
+        // A single run keeps the shape it had before several commands could be given.
+        Ok(match reports {
+            [report] => serde_json::to_string(report)?,
+            reports => serde_json::to_string(reports)?,
+        })
+    }
+
+    fn to_yaml(reports: &[impl serde::Serialize]) -> Result<String> {
+        // Shaped the same as `to_json`.
+        Ok(match reports {
+            [report] => serde_yaml::to_string(report)?,
+            reports => serde_yaml::to_string(reports)?,
+        })
+    }
+
+    fn to_csv(rows: Vec<Map<String, Value>>, delimiter: u8, header: bool) -> Result<String> {
+        let rows: Vec<Map<String, Value>> = rows
+            .into_iter()
+            .map(|row| flatten(row).into_iter().collect())
+            .collect();
+        let keys = keys(rows.iter().map(Map::keys));
+
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(Vec::new());
+
+        if header && !rows.is_empty() {
+            writer.write_record(&keys)?;
+        }
+
+        for row in rows {
+            // Left empty for a field this sample doesn't have.
+            writer.write_record(keys.iter().map(|key| match row.get(key) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(string)) => string.clone(),
+                Some(value) => value.to_string(),
+            }))?;
+        }
+
+        let bytes = writer
+            .into_inner()
+            .with_context(|| "failed to flush csv writer")?;
+
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    let (columns, time_format) = (rendering.columns, rendering.time_format);
+
+    // Written as is, without going through `select`.
+    let plain = columns.is_empty() && time_format == TimeFormat::EpochMs;
+
+    // `relative` timestamps count from the start of the sample's own run.
+    let start = |report: &Report| report.metadata.start_time.unwrap_or_default();
+
+    let rows = || {
+        reports
+            .iter()
+            .flat_map(|report| {
+                report
+                    .samples
+                    .iter()
+                    .map(move |sample| select(sample, columns, time_format, start(report)))
+            })
+            .collect::<Result<Vec<_>>>()
+    };
+
+    let selected = || {
+        reports
+            .iter()
+            .map(|report| {
+                Ok(Selected {
+                    metadata: report.metadata,
+                    samples: report
+                        .samples
+                        .iter()
+                        .map(|sample| select(sample, columns, time_format, start(report)))
+                        .collect::<Result<_>>()?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+    };
+
+    match rendering.format {
+        Format::Csv => write!(writer, "{}", to_csv(rows()?, b',', rendering.csv_header)?)?,
+        Format::Tsv => write!(writer, "{}", to_csv(rows()?, b'\t', rendering.csv_header)?)?,
+        Format::Json if rendering.legacy_json => {
+            writeln!(writer, "{}", serde_json::to_string(&rows()?)?)?;
+        }
+        Format::Json if !plain => writeln!(writer, "{}", to_json(&selected()?)?)?,
+        Format::Json => writeln!(writer, "{}", to_json(reports)?)?,
+        Format::Yaml if !plain => write!(writer, "{}", to_yaml(&selected()?)?)?,
+        Format::Yaml => write!(writer, "{}", to_yaml(reports)?)?,
+        Format::Html => write!(writer, "{}", html::render(reports)?)?,
+        Format::Markdown => write!(
+            writer,
+            "{}",
+            markdown::render(reports, rendering.mem_unit, rendering.full)?
+        )?,
+        #[cfg(feature = "parquet")]
+        Format::Parquet => {
+            let samples: Vec<&Samples> = reports.iter().flat_map(|report| report.samples).collect();
+            writer.write_all(&parquet::render(&samples)?)?;
+        }
+        // Rejected before getting here.
+        #[cfg(not(feature = "parquet"))]
+        Format::Parquet => {}
+        #[cfg(feature = "msgpack")]
+        Format::Msgpack => writer.write_all(&msgpack::render(reports)?)?,
+        #[cfg(not(feature = "msgpack"))]
+        Format::Msgpack => {}
+        Format::Prometheus => write!(writer, "{}", prometheus::render(reports))?,
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => sqlite::write(rendering.output_path, reports)?,
+        #[cfg(not(feature = "sqlite"))]
+        Format::Sqlite => {}
+        Format::Auto => unreachable!("resolved before rendering"),
+        Format::Ndjson => {
+            for row in rows()? {
+                serde_json::to_writer(&mut *writer, &row)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The fields of `sample` picked with `--columns`, in the order they were given, or all of them if none were,
+/// with its timestamp in `time_format` relative to the run's `start`.
+fn select(
+    sample: &Samples,
+    columns: &[String],
+    time_format: TimeFormat,
+    start: u64,
+) -> Result<Map<String, Value>> {
+    let mut row = row(sample)?;
+
+    if let Some(timestamp) = row.get_mut("timestamp") {
+        *timestamp = time_format.format(sample.timestamp, start);
+    }
+
+    if columns.is_empty() {
+        return Ok(row);
+    }
+
+    // Optional fields that are left out when absent stay left out.
+    Ok(columns
+        .iter()
+        .filter_map(|column| row.remove_entry(column))
+        .collect())
+}
+
+/// Bails on any of `columns` that isn't a field of the samples, listing those that are, or when `format`
+/// can't leave any out or change how `timestamp` is written.
+fn check_columns(columns: &[String], time_format: TimeFormat, format: Format) -> Result<()> {
+    let reshapes = matches!(
+        format,
+        Format::Csv | Format::Tsv | Format::Json | Format::Yaml | Format::Ndjson
+    );
+
+    if time_format != TimeFormat::EpochMs && !reshapes {
+        anyhow::bail!(
+            "`--time-format` only works with the csv, tsv, json, yaml and ndjson formats, not `{format}`"
+        );
+    }
+
+    if columns.is_empty() {
+        return Ok(());
+    }
+
+    if !reshapes {
+        anyhow::bail!(
+            "`--columns` only works with the csv, tsv, json, yaml and ndjson formats, not `{format}`"
+        );
+    }
+
+    let schema = schemars::generate::SchemaSettings::default()
+        .for_serialize()
+        .into_generator()
+        .into_root_schema_for::<Samples>();
+
+    let fields: Vec<&String> = schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .map(|properties| properties.keys().collect())
+        .unwrap_or_default();
+
+    if let Some(unknown) = columns.iter().find(|column| !fields.contains(column)) {
+        anyhow::bail!(
+            "unknown column `{unknown}`, expected one of: {}",
+            fields
+                .iter()
+                .map(|field| field.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Date and time in UTC of `millis` since the Unix epoch, like `2024-05-01T12:30:00.250Z`.
+fn rfc3339(millis: u64) -> String {
+    let (days, millis) = (millis / 86_400_000, millis % 86_400_000);
+    let (hour, minute, second) = (millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60);
+
+    // Civil date from days since the epoch, from Howard Hinnant's `civil_from_days`.
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{:03}Z",
+        millis % 1000
+    )
+}
+
+/// The signal that killed the process exiting with `status`, if one did.
+#[cfg(unix)]
+fn signal(status: ExitStatus) -> Option<i32> {
+    std::os::unix::process::ExitStatusExt::signal(&status)
+}
+
+#[cfg(not(unix))]
+fn signal(_: ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Each program given, as the command line to run along with what it's labeled by default.
+fn programs(cli: &Cli) -> Result<Vec<(Vec<String>, String)>> {
+    // A lone argument is split like a shell would, which is how `--cmd` and quoted programs come in, unless
+    // it's handed to an actual one with `--shell`.
+    let split = |program: &String| {
+        if cli.shell {
+            return Ok(shell(program));
+        }
+
+        shell_words::split(program).with_context(|| format!("failed to parse program `{program}`"))
+    };
+
+    let mut programs = Vec::new();
+
+    match cli.program.as_slice() {
+        [] => {}
+        [program] => programs.push((split(program)?, program.clone())),
+        // Joined as is, so `peek --shell ls '|' wc -l` pipes rather than passing `|` to `ls`.
+        command if cli.shell => {
+            let program = command.join(" ");
+            programs.push((shell(&program), program));
+        }
+        command => programs.push((command.to_vec(), shell_words::join(command))),
+    }
+
+    for program in &cli.cmd {
+        programs.push((split(program)?, program.clone()));
+    }
+
+    Ok(programs)
+}
+
+/// The command line running `program` through the platform's shell, for `--shell`.
+fn shell(program: &str) -> Vec<String> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+
+    vec![shell.to_owned(), flag.to_owned(), program.to_owned()]
+}
+
+/// `value` rounded to `places` decimal places.
+fn round(value: f64, places: u32) -> f64 {
+    let scale = 10_f64.powi(places as i32);
+    (value * scale).round() / scale
+}
+
+/// Milliseconds since the Unix epoch.
+fn unix_millis() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64)
+}
+
+/// Every key of `rows` in the order they have them, so a field only some rows have still gets a column,
+/// next to the ones it's declared between.
+fn keys<'a, K: IntoIterator<Item = &'a String>>(rows: impl IntoIterator<Item = K>) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+
+    for row in rows {
+        let row: Vec<&String> = row.into_iter().collect();
+
+        if row.iter().all(|key| seen.contains(*key)) {
+            continue;
+        }
+
+        // Where the next key goes if it's new, right after the one before it in this row.
+        let mut at = 0;
+
+        for key in row {
+            match keys.iter().position(|known| known == key) {
+                Some(i) => at = i + 1,
+                None => {
+                    keys.insert(at, key.clone());
+                    seen.insert(key);
+                    at += 1;
+                }
+            }
+        }
+    }
+
+    keys
+}
+
+/// Splits lists like `cpu_cores` and groups like `ctx_switches` into a field per element, for formats
+/// where a field can only hold a single value.
+fn flatten(row: Map<String, Value>) -> Vec<(String, Value)> {
+    row.into_iter()
+        .flat_map(|(key, value)| match value {
+            Value::Array(values) => values
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| (format!("{key}_{i}"), value))
+                .collect(),
+            Value::Object(values) => values
+                .into_iter()
+                .map(|(field, value)| (format!("{key}_{field}"), value))
+                .collect(),
+            value => vec![(key, value)],
+        })
+        .collect()
+}
+
+/// Serializes `value` into its fields, in declaration order.
+fn row(value: impl serde::Serialize) -> Result<Map<String, Value>> {
+    // Going through a string keeps `f32`s as written, `to_value` would widen `3.14` to `3.140000104904175`.
+    match serde_json::from_str(&serde_json::to_string(&value)?)? {
+        Value::Object(map) => Ok(map),
+        _ => anyhow::bail!("expected a struct to serialize into a row"),
+    }
+}
+
+/// Sums the values that could be read, or `None` if none of them could.
+fn sum_available<T: std::ops::Add<Output = T>>(
+    values: impl Iterator<Item = Option<T>>,
+) -> Option<T> {
+    values.flatten().reduce(|total, value| total + value)
+}
+
+/// Refreshes only CPU usage, for `root` alone or for every process when it's `None`.
+fn refresh_cpu_usage(system: &mut System, root: Option<Pid>) {
+    match root {
+        Some(root) => {
+            system.refresh_process_specifics(root, ProcessRefreshKind::new().with_cpu());
+        }
+        None => system.refresh_processes_specifics(ProcessRefreshKind::new().with_cpu()),
+    }
+}
+
+/// Returns the `root` process along with every process whose parent chain leads back to it.
+fn process_tree(system: &System, root: Pid) -> Vec<&Process> {
+    system
+        .processes()
+        .values()
+        .filter(|process| {
+            let mut current = Some(process.pid());
+
+            while let Some(pid) = current {
+                if pid == root {
+                    return true;
+                }
+
+                current = system.process(pid).and_then(|parent| parent.parent());
+            }
+
+            false
+        })
+        .collect()
+}
+
+/// A single program, or process, being sampled along with everything recorded about it.
+struct Run {
+    target: Target,
+    uuid: Uuid,
+    label: String,
+    /// The program and its arguments, empty if they couldn't be read for an attached process.
+    command: Vec<String>,
+    /// What the OS reports the root process was started with, see `Metadata::cmdline`.
+    cmdline: Vec<String>,
+    cwd: Option<PathBuf>,
+    start_time: Option<u64>,
+    end_time: Option<u64>,
+    process_start_time: Option<u64>,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    /// Of every sample taken before `--bucket`, with `--histogram`.
+    histogram: Option<Histograms>,
+    peaks: Peaks,
+    /// Samples recorded, which not all formats and options keep in `samples`.
+    collected: u64,
+    /// The first sample, and its value, that went over `--fail-if-cpu-over`.
+    cpu_over: Option<(u64, f32)>,
+    /// The first sample, and its value, that went over `--fail-if-mem-over`.
+    mem_over: Option<(u64, u64)>,
+    stdout: Option<Captured>,
+    stderr: Option<Captured>,
+    /// Children starting and exiting, with `--trace-spawns`.
+    events: Vec<spawns::SpawnEvent>,
+    /// Marked from outside with `--markers` or `--marker-file`.
+    markers: Vec<markers::Marker>,
+    samples: Vec<Samples>,
+}
+
+impl Run {
+    fn new(target: Target, command: Vec<String>, cwd: Option<PathBuf>, label: String) -> Self {
+        Self {
+            target,
+            uuid: Uuid::new_v4(),
+            label,
+            command,
+            cmdline: Vec::new(),
+            cwd,
+            start_time: None,
+            end_time: None,
+            process_start_time: None,
+            exit_code: None,
+            signal: None,
+            histogram: None,
+            peaks: Peaks::default(),
+            collected: 0,
+            cpu_over: None,
+            mem_over: None,
+            stdout: None,
+            stderr: None,
+            events: Vec::new(),
+            markers: Vec::new(),
+            samples: Vec::with_capacity(1024),
+        }
+    }
+
+    /// Keeps whatever the program wrote to stdout and stderr with `--capture`.
+    fn capture(&mut self, captures: Option<(Capture, Capture)>) {
+        if let Some((stdout, stderr)) = captures {
+            self.stdout = Some(stdout.finish());
+            self.stderr = Some(stderr.finish());
+        }
+    }
+}
+
+enum Target {
+    Spawn(Program),
+    Attach(usize),
+    /// Every process whose name matches, summed together.
+    Match(Regex),
+}
+
+#[derive(Debug)]
+struct Program {
+    command: String,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    clear_env: bool,
+    cwd: PathBuf,
+    stdin: Option<PathBuf>,
+    /// Whether the program gets no stdin at all, instead of inheriting `peek`'s.
+    null_stdin: bool,
+    stdout: Option<PathBuf>,
+    stderr: Option<PathBuf>,
+    /// Whether `peek` writes its own output to stdout, where the program's output would corrupt it.
+    null_stdout: bool,
+    /// The same for stderr, with `--output stderr`.
+    null_stderr: bool,
+    /// Bytes of stdout and stderr each to keep with `--capture`.
+    capture: Option<u64>,
+    /// Whether to account for the whole tree with a job on Windows, for `--tree`.
+    tree: bool,
+}
+
+impl Program {
+    pub fn new(command: Vec<String>, cli: &Cli) -> Result<Self> {
+        let mut command = command.into_iter();
+
+        let cwd = match &cli.cwd {
+            Some(cwd) => {
+                let resolved = cwd.canonicalize().with_context(|| {
+                    format!("working directory `{}` does not exist", cwd.display())
+                })?;
+
+                if !resolved.is_dir() {
+                    anyhow::bail!("working directory `{}` is not a directory", cwd.display());
+                }
+
+                resolved
+            }
+            None => std::env::current_dir()?,
+        };
+
+        Ok(Self {
+            command: command.next().with_context(|| "no program was given")?,
+            args: command.collect(),
+            env: cli.env.clone(),
+            clear_env: cli.clear_env,
+            cwd,
+            stdin: cli.child_stdin.clone(),
+            null_stdin: cli.no_stdin,
+            stdout: cli.child_stdout.clone(),
+            stderr: cli.child_stderr.clone(),
+            // Stdout is free for the program when `peek` writes nothing there.
+            null_stdout: matches!(cli.output, Output::Stdout | Output::Both) && !cli.no_output,
+            null_stderr: cli.output == Output::Stderr && !cli.no_output,
+            capture: cli.capture.then_some(cli.capture_limit),
+            tree: cli.tree,
+        })
+    }
+
+    /// Builds a `Command` that runs `program` with the configured arguments, environment and stdio.
+    fn build(&self, program: impl AsRef<OsStr>) -> Result<Command> {
+        fn create(path: &Path, stream: &str) -> Result<File> {
+            File::create(path).with_context(|| {
+                format!(
+                    "failed to create `{}` for the program's {stream}",
+                    path.display()
+                )
+            })
+        }
+
+        let mut command = Command::new(program);
+
+        match &self.stdin {
+            Some(path) => {
+                command.stdin(File::open(path).with_context(|| {
+                    format!(
+                        "failed to open `{}` for the program's stdin",
+                        path.display()
+                    )
+                })?);
+            }
+            None if self.null_stdin => {
+                command.stdin(Stdio::null());
+            }
+            None => {}
+        }
+
+        match &self.stdout {
+            Some(path) => {
+                command.stdout(create(path, "stdout")?);
+            }
+            None if self.null_stdout => {
+                command.stdout(Stdio::null());
+            }
+            None => {}
+        }
+
+        match &self.stderr {
+            Some(path) => {
+                command.stderr(create(path, "stderr")?);
+            }
+            None if self.null_stderr => {
+                command.stderr(Stdio::null());
+            }
+            None => {}
+        }
+
+        if self.capture.is_some() {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+
+        if self.clear_env {
+            command.env_clear();
+        }
+
+        command
+            .current_dir(&self.cwd)
+            .args(&self.args)
+            .envs(self.env.iter().map(|(key, value)| (key, value)));
+
+        Ok(command)
+    }
+
+    /// Finds the executable to start, like a shell would: a bare name on the `PATH`, and anything with a
+    /// separator, like `./program`, relative to the working directory.
+    pub fn resolve(&self) -> Result<PathBuf> {
+        // Searched for on the `PATH` the program runs with, which `--env` and `--clear-env` can change.
+        let path = match self.env.iter().rev().find(|(key, _)| key == "PATH") {
+            Some((_, path)) => Some(path.into()),
+            None if self.clear_env => None,
+            None => std::env::var_os("PATH"),
+        };
+
+        which::which_in(&self.command, path, &self.cwd)
+            .map_err(|_| anyhow::anyhow!("command not found: `{}`", self.command))
+    }
+
+    pub fn run(&self) -> Result<RunningProgram> {
+        let mut child = self
+            .build(self.resolve()?)?
+            .spawn()
+            .with_context(|| format!("failed to run `{}`", self.command))?;
+
+        let pid = child.id() as usize;
+        let (status_tx, status_rx) = channel();
+
+        let job = self.tree.then(|| job::Job::new(&child)).flatten();
+
+        let captures = self.capture.and_then(|limit| {
+            Some((
+                Capture::new(child.stdout.take()?, limit),
+                Capture::new(child.stderr.take()?, limit),
+            ))
+        });
+
+        let waiter = std::thread::spawn(move || {
+            // Nothing is sent if waiting fails, which `run` sees the same as the program disappearing.
+            if let Ok(status) = child.wait() {
+                // Only fails once `peek` stopped listening, when the status isn't needed anymore.
+                let _ = status_tx.send(status);
+            }
+        });
+
+        Ok(RunningProgram {
+            pid,
+            finished_running: status_rx,
+            waiter,
+            captures,
+            job,
+        })
+    }
+}
+
+/// Everything known about a run besides its samples.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+// Lets `peek replay` read files from before a field was added.
+#[serde(default)]
+struct Metadata {
+    uuid: Uuid,
+    label: String,
+    /// `None` if it couldn't be read for an attached process.
+    command: Option<String>,
+    args: Vec<String>,
+    /// The command line of the process actually sampled, as the OS reports it when sampling starts. Tells
+    /// apart processes sharing a name when attached with `--pid` or `--name`, and for a spawned program shows
+    /// what `command` resolved to. With `--follow-name` it's that of the last process followed, and with
+    /// `--match` it's empty.
+    cmdline: Vec<String>,
+    /// Unix epoch milliseconds of when sampling started, including any `--warmup`.
+    start_time: Option<u64>,
+    /// Unix epoch milliseconds of when sampling stopped.
+    end_time: Option<u64>,
+    /// Unix epoch milliseconds of when the process started, to the second. Long before `start_time` for an
+    /// attached process, and `None` with `--match`.
+    process_start_time: Option<u64>,
+    host: Option<String>,
+    /// Name and version of the operating system.
+    os: Option<String>,
+    kernel: Option<String>,
+    /// Brand of the CPU as the OS reports it, like `AMD Ryzen 9 5950X 16-Core Processor`.
+    cpu_model: Option<String>,
+    /// Physical memory of the machine.
+    total_mem: Memory,
+    /// Logical cores on the system, what 100% of `cpu` is per core.
+    cores: usize,
+    /// Shortest wait between samples, the only one without `--adaptive`.
+    interval_ms: u64,
+    /// Longest wait between samples with `--adaptive`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_interval_ms: Option<u64>,
+    cpu_scale: CpuScale,
+    /// Unit of every memory value.
+    mem_unit: MemUnit,
+    /// `None` when the program was interrupted, attached to, or killed by a signal.
+    exit_code: Option<i32>,
+    /// The signal that killed the program on its own, like `11` for a segfault, in which case `peek` exits
+    /// with `128` plus it. Unix only, and not set when `peek` itself stopped the program.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signal: Option<i32>,
+    /// Absolute working directory of the program.
+    cwd: Option<PathBuf>,
+    peak_cpu: f32,
+    peak_mem: Memory,
+    peak_virt_mem: Memory,
+    /// Bytes read from and written to disk over the whole run, as of the last sample.
+    disk_read: u64,
+    disk_write: u64,
+    /// What the program wrote with `--capture`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stdout: Option<Captured>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stderr: Option<Captured>,
+    /// Children of the program starting and exiting over the run, with `--trace-spawns`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    events: Vec<spawns::SpawnEvent>,
+    /// Points marked with `--markers` or `--marker-file` over the run.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    markers: Vec<markers::Marker>,
+    /// The run given with `--baseline`, whose mean was subtracted from every sample.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    baseline: Option<PathBuf>,
+    /// How `cpu` and `mem` were spread over the run, with `--histogram`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    histogram: Option<Histograms>,
+}
+
+/// Everything written out for a run.
+#[derive(serde::Serialize, schemars::JsonSchema)]
+struct Report<'a> {
+    metadata: &'a Metadata,
+    samples: &'a [Samples],
+}
+
+/// High-water marks across every sample, kept up to date as samples are taken.
+#[derive(Debug, Default)]
+struct Peaks {
+    cpu: f32,
+    mem: u64,
+    virt_mem: u64,
+    /// Cumulative, so the highest is the total.
+    disk_read: u64,
+    disk_write: u64,
+}
+
+impl Peaks {
+    fn update(&mut self, sample: &Samples) {
+        self.cpu = self.cpu.max(sample.cpu);
+        self.mem = self.mem.max(sample.mem.bytes);
+        self.virt_mem = self.virt_mem.max(sample.virt_mem.bytes);
+        self.disk_read = self.disk_read.max(sample.disk_read);
+        self.disk_write = self.disk_write.max(sample.disk_write);
+    }
+}
+
+/// An amount of memory, serialized in the unit chosen with `--mem-unit`.
+#[derive(Debug, Default, Copy, Clone)]
+struct Memory {
+    /// `sysinfo` reports memory in bytes on every platform, this is never converted.
+    bytes: u64,
+    unit: MemUnit,
+}
+
+impl serde::Serialize for Memory {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.unit {
+            MemUnit::Bytes => serializer.serialize_u64(self.bytes),
+            unit => serializer.serialize_f64(unit.convert(self.bytes)),
+        }
+    }
+}
+
+impl schemars::JsonSchema for Memory {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "Memory".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "description": "An amount of memory in the run's `mem_unit`, an integer for bytes.",
+            "type": "number",
+            "minimum": 0
+        })
+    }
+}
+
+/// Reads a value in bytes, which `peek replay` converts values in other units to beforehand. The unit is
+/// only known from the metadata, so it needs setting after.
+impl<'de> serde::Deserialize<'de> for Memory {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self {
+            bytes: <f64 as serde::Deserialize>::deserialize(deserializer)?.round() as u64,
+            unit: MemUnit::Bytes,
+        })
+    }
+}
+
+struct RunningProgram {
+    pid: usize,
+    finished_running: Receiver<ExitStatus>,
+    /// Thread waiting on the program, which finishes once it has been reaped.
+    waiter: std::thread::JoinHandle<()>,
+    /// Readers of stdout and stderr with `--capture`.
+    captures: Option<(Capture, Capture)>,
+    /// The job the program runs in with `--tree`, only ever created on Windows.
+    job: Option<job::Job>,
+}
+
+/// One sample of a run.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+// Defaulted like `Metadata`.
+#[serde(default)]
+struct Samples {
+    /// Only with `--per-row-uuid`, otherwise the run's `uuid` is in its metadata.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uuid: Option<Uuid>,
+    /// Given with `--label`, otherwise the program as it was given or the name of the attached process.
+    label: String,
+    /// Set by the last line written to `--phase-file` before the sample, like `warmup` or `teardown`, and
+    /// empty until the first. Absent without `--phase-file`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phase: Option<String>,
+    sample: u64,
+    /// Unix epoch milliseconds of when the sample was taken.
+    timestamp: u64,
+    /// Milliseconds actually elapsed since the previous sample, which drifts from `--interval` with load.
+    /// `0` for the first sample.
+    delta_ms: u64,
+    /// `null` with `--match`, which has no single process.
+    pid: Option<usize>,
+    /// Name of the process, or the pattern with `--match`.
+    name: String,
+    /// Seconds since the process started, absent with `--match`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uptime_secs: Option<u64>,
+    /// Number of processes the metrics were summed over. Greater than `1` only with `--tree` or `--match`.
+    process_count: usize,
+    cpu: f32,
+    /// Trailing average of `cpu` over the last `--smooth` samples, fewer at the start. Only present with
+    /// `--smooth`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_smoothed: Option<f32>,
+    /// Resident memory.
+    mem: Memory,
+    /// Virtual memory.
+    virt_mem: Memory,
+    /// Memory swapped out to disk. Only available on Linux, absent elsewhere.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    swap: Option<Memory>,
+    /// Only available on Linux, `null` elsewhere.
+    threads: Option<u64>,
+    /// Open file descriptors, summed over the process tree. Only available on Linux, `null` elsewhere or
+    /// when `/proc/<pid>/fd` can't be read.
+    open_files: Option<u64>,
+    /// Cumulative `voluntary` and `involuntary` context switches. Only available on Linux, absent elsewhere.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ctx_switches: Option<procfs::ContextSwitches>,
+    /// Cumulative `minor` and `major` page faults. Only available on Linux, absent elsewhere.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_faults: Option<procfs::PageFaults>,
+    /// `nice` and `priority` columns of the root process even with `--tree`, as they don't add up. Only available on
+    /// Linux, absent elsewhere and with `--match`.
+    #[serde(flatten)]
+    priority: Option<procfs::Priority>,
+    /// `cgroup_mem` and `cgroup_mem_max` with `--cgroup`, absent when not under cgroup v2.
+    #[serde(flatten)]
+    cgroup: Option<Cgroup>,
+    /// `mem_limit` and `mem_limit_pct`, absent when the process isn't limited.
+    #[serde(flatten)]
+    limit: Option<MemLimit>,
+    disk_read: u64,
+    disk_write: u64,
+    /// Bytes per second read since the previous sample.
+    disk_read_rate: f64,
+    /// Bytes per second written since the previous sample.
+    disk_write_rate: f64,
+    /// `net_rx`, `net_tx` and their rates in the root process' network namespace, see `procfs::net_dev`.
+    /// Only available on Linux, absent elsewhere and with `--match`.
+    #[serde(flatten)]
+    network: Option<Network>,
+    /// Power drawn by every CPU package together since the previous sample, see `procfs::package_energy`.
+    /// System-wide rather than the process' own, so only telling on an otherwise idle machine, and it
+    /// leaves out the rest of the machine like memory and disks. Linux with RAPL only, and usually root,
+    /// absent elsewhere.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    power_watts: Option<f64>,
+    /// What the whole system is doing alongside the process. Only present with `--system`.
+    #[serde(flatten)]
+    system: Option<SystemUsage>,
+    /// `cpu_stddev`, `mem_stddev` and `runs`, only present with `--average`.
+    #[serde(flatten)]
+    spread: Option<Spread>,
+    /// System-wide usage of each core, not just that of the process. Only present with `--per-core`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_cores: Option<Vec<f32>>,
+}
+
+impl Samples {
+    /// Rounds the CPU fields to `precision` decimal places, 2 without it, and every other float field only
+    /// when it's given.
+    fn round(&mut self, precision: Option<u32>) {
+        let cpu = |value: f32| round(f64::from(value), precision.unwrap_or(2)) as f32;
+
+        self.cpu = cpu(self.cpu);
+        self.cpu_smoothed = self.cpu_smoothed.map(cpu);
+
+        if let Some(cores) = &mut self.cpu_cores {
+            for core in cores {
+                *core = cpu(*core);
+            }
+        }
+
+        if let Some(system) = &mut self.system {
+            system.system_cpu = cpu(system.system_cpu);
+        }
+
+        if let Some(spread) = &mut self.spread {
+            spread.cpu_stddev = cpu(spread.cpu_stddev);
+        }
+
+        let Some(precision) = precision else {
+            return;
+        };
+
+        let float = |value: &mut f64| *value = round(*value, precision);
+
+        float(&mut self.disk_read_rate);
+        float(&mut self.disk_write_rate);
+
+        if let Some(watts) = &mut self.power_watts {
+            float(watts);
+        }
+
+        if let Some(network) = &mut self.network {
+            float(&mut network.net_rx_rate);
+            float(&mut network.net_tx_rate);
+        }
+
+        if let Some(limit) = &mut self.limit {
+            float(&mut limit.mem_limit_pct);
+        }
+
+        if let Some(system) = &mut self.system {
+            for load in [&mut system.load_1, &mut system.load_5, &mut system.load_15]
+                .into_iter()
+                .flatten()
+            {
+                float(load);
+            }
+        }
+    }
+}
+
+/// Memory use of the root process' cgroup, what the kernel compares against the limit before OOM killing.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+struct Cgroup {
+    /// Everything charged to the cgroup, including page cache and other processes in it.
+    cgroup_mem: Memory,
+    /// `null` when the cgroup is unlimited.
+    cgroup_mem_max: Option<Memory>,
+}
+
+/// How close the process is to the most memory it's allowed, see `procfs::mem_limit`.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+struct MemLimit {
+    mem_limit: Memory,
+    /// `mem` as a percentage of a cgroup's `memory.max`, or `virt_mem` of an `RLIMIT_AS`, which caps the
+    /// address space rather than what's resident.
+    mem_limit_pct: f64,
+}
+
+impl MemLimit {
+    fn new(limit: procfs::MemLimit, mem: u64, virt_mem: u64, unit: MemUnit) -> Self {
+        let (used, limit) = match limit {
+            procfs::MemLimit::Cgroup(limit) => (mem, limit),
+            procfs::MemLimit::AddressSpace(limit) => (virt_mem, limit),
+        };
+
+        Self {
+            mem_limit: Memory { bytes: limit, unit },
+            mem_limit_pct: used as f64 / limit as f64 * 100.0,
+        }
+    }
+}
+
+/// How far apart the samples averaged into one with `--average` were.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+struct Spread {
+    cpu_stddev: f32,
+    mem_stddev: Memory,
+    /// Repeats that got as far as this sample.
+    runs: usize,
+}
+
+/// Usage of the whole system, to tell a program slowed down by itself from one on a busy host.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+struct SystemUsage {
+    /// Usage of every core together, from 0 to 100 regardless of `--cpu-scale`.
+    system_cpu: f32,
+    system_mem_total: Memory,
+    system_mem_used: Memory,
+    /// What can still be handed out, counting caches the kernel would drop.
+    system_mem_available: Memory,
+    /// Load averages over the last 1, 5 and 15 minutes. Unix only, `null` elsewhere.
+    load_1: Option<f64>,
+    load_5: Option<f64>,
+    load_15: Option<f64>,
+}
+
+/// Network traffic, cumulative in bytes and in bytes per second since the previous sample.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+struct Network {
+    net_rx: u64,
+    net_tx: u64,
+    net_rx_rate: f64,
+    net_tx_rate: f64,
+}
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Subcommand>,
+    /// The program to run followed by its arguments, which are passed on as is, so `peek cargo build
+    /// --release` needs no quoting. Options for `peek` itself go before the program. A single argument is
+    /// split like a shell would, so `peek "cargo build --release"` works too.
+    #[arg(
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        required_unless_present_any = ["pid", "name", "config", "cmd", "pattern", "print_schema"]
+    )]
+    program: Vec<String>,
+    /// Run several programs one after the other, each recorded as its own run labeled by the command.
+    /// Can be given multiple times. With JSON the output becomes an array of each run's report.
+    #[arg(long, value_name = "PROGRAM", conflicts_with_all = ["program", "pid", "label"])]
+    cmd: Vec<String>,
+    /// Run the program, and each `--cmd`, through `sh -c`, or `cmd /C` on Windows, for pipes, redirects and
+    /// builtins, e.g. `peek --shell "grep foo big.txt | wc -l"`. The process sampled is then the shell, so
+    /// pair it with `--tree` to include what it runs, with the shell itself counted in the totals too.
+    #[arg(long, conflicts_with_all = ["pid", "name", "pattern"])]
+    shell: bool,
+    /// Attach to an already running process instead of spawning a program.
+    #[arg(long, short, group = "attach", conflicts_with = "program")]
+    pid: Option<usize>,
+    /// Attach to the running process with exactly this name, like `--pid` with its pid. Fails when no
+    /// process or several have the name, listing the pids of those to pick from.
+    #[arg(long, group = "attach", conflicts_with_all = ["program", "cmd", "pattern"])]
+    name: Option<String>,
+    /// When the process given with `--pid` or `--name` is gone, look for one with the same name for up to this many
+    /// seconds and carry on sampling that, to keep following a worker its supervisor restarts. The switch
+    /// shows in each sample's `pid`. If several share the name, the one started last is followed, which
+    /// may not be the one meant. Defaults to 10 seconds.
+    #[arg(long, value_name = "SECONDS", requires = "attach", num_args = 0..=1, default_missing_value = "10")]
+    follow_name: Option<u64>,
+    /// Sample every running process whose name matches this regex, summed together. Processes are added
+    /// and dropped as they start and exit, and no samples are taken while none match. Runs until
+    /// interrupted or `--duration` is up.
+    #[arg(long = "match", value_name = "REGEX", conflicts_with_all = ["program", "pid", "cmd", "tree"])]
+    pattern: Option<Regex>,
+    /// File to write to with `--output file` or `--output both`. Defaults to `peek.<format>` in the current
+    /// directory.
+    #[arg(long)]
+    path: Option<PathBuf>,
+    /// Write to a file in this directory named after `--label`, or after the run's `uuid` without one, so
+    /// each invocation gets its own file. Created if it doesn't exist, and implies `--output file` unless
+    /// `--output both` is given.
+    #[arg(long, value_name = "DIR", conflicts_with = "path")]
+    output_dir: Option<PathBuf>,
+    /// Create the directory `--path` is in if it doesn't exist, instead of failing.
+    #[arg(long)]
+    mkdir: bool,
+    #[arg(long, short, default_value = "stdout")]
+    output: Output,
+    #[arg(value_enum, long, short, default_value = "auto")]
+    format: Format,
+    /// Milliseconds to wait between samples. Below 200ms memory and disk are still sampled at this rate,
+    /// but CPU usage is unreliable that fast, so it's only refreshed every 200ms and repeats in between.
+    #[arg(long, short)]
+    interval: Option<u64>,
+    /// Sample less often while the program is steady. Every sample where `cpu` moved by under 5 points and
+    /// `mem` by under 5% doubles the wait, up to `--max-interval`, and any bigger change drops it straight
+    /// back to `--interval`.
+    #[arg(long)]
+    adaptive: bool,
+    /// Longest wait in milliseconds `--adaptive` backs off to. Defaults to 5000.
+    #[arg(long, value_name = "MS", requires = "adaptive")]
+    max_interval: Option<u64>,
+    /// Only write a sample when `cpu` moved by at least TOLERANCE points or `mem` by more than TOLERANCE
+    /// percent since the last one written, leaving out idle stretches. Samples are still taken every
+    /// `--interval`, so `sample` has gaps, and `delta_ms` and the rates only cover the time since the one
+    /// before, written or not.
+    #[arg(long, value_name = "TOLERANCE", num_args = 0..=1, default_missing_value = "5")]
+    on_change: Option<f32>,
+    /// With `--on-change`, only write a sample once `mem` moved by at least this many bytes since the last
+    /// one written, whatever `cpu` did, so a leak hunt isn't drowned in small fluctuations. Takes the same
+    /// suffixes as `--fail-if-mem-over`.
+    #[arg(long, value_name = "BYTES", value_parser = parse_bytes, requires = "on_change")]
+    min_mem_delta: Option<u64>,
+    /// Tag the run with a name, recorded in the metadata and every sample. Defaults to the program.
+    #[arg(long, short)]
+    label: Option<String>,
+    /// Stop sampling after this many seconds.
+    #[arg(long, short)]
+    duration: Option<u64>,
+    /// Milliseconds to wait after the program starts before recording samples, so the data reflects steady
+    /// state. Without it the first sample's `cpu` is usually 0, as usage can only be measured between two
+    /// samples. Doesn't count towards `--duration`.
+    #[arg(long, value_name = "MS")]
+    warmup: Option<u64>,
+    /// Output of an earlier run, like of the system sitting idle, whose mean `cpu`, `mem`, `virt_mem` and disk
+    /// rates are subtracted from every sample to leave only what the program adds. Values don't go below 0.
+    /// The peaks and the `--fail-if-*` limits are still of the samples as taken. Read like `peek replay`
+    /// reads its input, with ndjson taken to be in bytes.
+    #[arg(long, value_name = "PATH")]
+    baseline: Option<PathBuf>,
+    /// Milliseconds to wait for a spawned program to show up among the running processes before sampling
+    /// it, which can lag behind starting it on some systems. Waiting ends early once the program exits.
+    #[arg(long, value_name = "MS", default_value = "1000")]
+    startup_timeout: u64,
+    /// Stop sampling once this many samples have been collected, or with `--sample-limit-action ring` keep
+    /// only the last this many.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_samples: Option<u64>,
+    /// What to do once `--max-samples` is reached.
+    #[arg(value_enum, long, default_value_t, requires = "max_samples")]
+    sample_limit_action: SampleLimitAction,
+    /// Round every float field of the samples to this many decimal places, like the rates and `mem_limit_pct`.
+    /// Without it only the CPU fields are rounded, to 2. Memory in another `--mem-unit` than bytes is left as
+    /// converted.
+    #[arg(long, value_name = "PLACES")]
+    precision: Option<u32>,
+    /// Roll the samples up into one per this many seconds before writing them out, to shrink long runs. CPU
+    /// and rates are averaged, memory and counts take the max, disk I/O what was read and written within the
+    /// bucket and other cumulative totals their last value. Not for ndjson, which is written as it's sampled.
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    bucket: Option<u64>,
+    /// Sum the metrics of the process and all of its descendants.
+    #[arg(long)]
+    tree: bool,
+    /// Record each child of the program starting and exiting as it's seen in the tree, as `events` in the
+    /// metadata, to see e.g. when a test runner forked its workers. Children that start and exit between two
+    /// samples are missed.
+    #[arg(long, requires = "tree")]
+    trace_spawns: bool,
+    /// Run each program this many times, recorded as runs labeled `#1` to `#N` like with `--cmd`. With
+    /// `--summary`, how much each metric varied between them is printed too.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["pid", "name", "pattern"])]
+    repeat: Option<u64>,
+    /// Fold the runs of `--repeat` into one, each sample the mean of the samples at the same index of every run
+    /// that got that far, with `cpu_stddev`, `mem_stddev` and the number of `runs` behind it. Not for ndjson,
+    /// which is written as it's sampled.
+    #[arg(long, requires = "repeat", conflicts_with = "count_only")]
+    average: bool,
+    /// Record a marker in the metadata's `markers` every time `peek` gets `SIGUSR1`, like with
+    /// `kill -USR1 <PID>` from a script when a load test begins. Unix only, use `--marker-file` elsewhere.
+    #[arg(long)]
+    markers: bool,
+    /// Record a marker labeled by each line appended to this file while sampling, like with
+    /// `echo "deploy started" >> FILE`. Created if it doesn't exist, and what's already in it is skipped.
+    #[arg(long, value_name = "FILE")]
+    marker_file: Option<PathBuf>,
+    /// Set the `phase` of every sample after each line appended to this file, like with
+    /// `echo steady >> FILE` from the script driving a test, to tell its stages apart. An empty line clears
+    /// it. Created if it doesn't exist, and what's already in it is skipped.
+    #[arg(long, value_name = "FILE")]
+    phase_file: Option<PathBuf>,
+    /// Record the system-wide usage of every core in each sample, as `cpu_cores`.
+    #[arg(long)]
+    per_core: bool,
+    /// Record the memory use and limit of the process' cgroup, which is what gets a container OOM killed,
+    /// as `cgroup_mem` and `cgroup_mem_max`. Linux with cgroup v2 only.
+    #[arg(long)]
+    cgroup: bool,
+    /// Record what the whole system is doing in each sample, to spot a busy host: its CPU usage as
+    /// `system_cpu`, its memory as `system_mem_total`, `system_mem_used` and `system_mem_available`, and its
+    /// load averages as `load_1`, `load_5` and `load_15` on Unix.
+    #[arg(long)]
+    system: bool,
+    /// Add `cpu_smoothed`, the average `cpu` over this many of the latest samples, to even out spikes.
+    #[arg(long, value_name = "WINDOW")]
+    smooth: Option<NonZeroUsize>,
+    /// Exit with 1 if `cpu` ever goes over this percentage, to hold a program to a resource budget in CI.
+    #[arg(long, value_name = "PCT")]
+    fail_if_cpu_over: Option<f32>,
+    /// Exit with 1 if `mem` ever goes over this many bytes. Takes a `K`, `M` or `G` suffix for KiB, MiB and
+    /// GiB, e.g. `512M`.
+    #[arg(long, value_name = "BYTES", value_parser = parse_bytes)]
+    fail_if_mem_over: Option<u64>,
+    /// Fit a line to `mem` over time once sampling is done, and print on stderr whether it grows steadily
+    /// enough to be a leak: an r² of at least 0.8 and growth over `--leak-threshold` across the run. Needs at
+    /// least 10 samples. Startup can look like growth, which `--warmup` leaves out.
+    #[arg(long)]
+    detect_leak: bool,
+    /// Exit with 1 if `--detect-leak` finds a probable leak. Implies `--detect-leak`.
+    #[arg(long)]
+    fail_on_leak: bool,
+    /// Bytes `mem` has to grow by across the run for `--detect-leak` to call it a leak. Takes the same
+    /// suffixes as `--fail-if-mem-over`.
+    #[arg(long, value_name = "BYTES", value_parser = parse_bytes, default_value = "1M")]
+    leak_threshold: u64,
+    /// What 100% of `cpu` means.
+    #[arg(value_enum, long, default_value = "core")]
+    cpu_scale: CpuScale,
+    /// Unit to report `mem` and `virt_mem` in, including in `--summary` and `--watch`. Bytes by
+    /// default, with `--watch` picking a readable unit on its own.
+    #[arg(value_enum, long)]
+    mem_unit: Option<MemUnit>,
+    /// Print min, max, mean and p95 of each metric to stderr once sampling is done.
+    #[arg(long)]
+    summary: bool,
+    /// Print a histogram of `cpu` and `mem` to stderr once sampling is done, and add the count of samples
+    /// in each bin to the metadata as `histogram`. Covers every sample taken, even with `--bucket`.
+    #[arg(long)]
+    histogram: bool,
+    /// How many equally wide bins `--histogram` splits the range of each metric into.
+    #[arg(long, value_name = "N", default_value = "10", requires = "histogram")]
+    bins: NonZeroUsize,
+    /// Add to the end of the output file instead of replacing it, to collect several runs into one
+    /// dataset. Only for ndjson, csv and tsv, which stay valid when appended to, and sqlite, which always
+    /// appends. CSV and TSV only get a header when the file is empty, so appended runs need the same
+    /// columns, e.g. the same `--per-core`.
+    #[arg(long)]
+    append: bool,
+    /// Only write these fields of each sample, in this order, e.g. `timestamp,cpu,mem`. Groups like
+    /// `ctx_switches` are selected whole. Only for csv, tsv, json, yaml and ndjson, the metadata is kept as
+    /// is.
+    #[arg(long, value_delimiter = ',', value_name = "COLUMNS")]
+    columns: Vec<String>,
+    /// How each sample's `timestamp` is written. Only for csv, tsv, json, yaml and ndjson, the metadata
+    /// always has unix epoch milliseconds. Anything but `epoch-ms` can't be read back by `peek replay`.
+    #[arg(value_enum, long, default_value_t)]
+    time_format: TimeFormat,
+    /// Flush ndjson output after every this many samples instead of after each one, trading how many could
+    /// be lost in a crash for fewer writes. Other formats are written all at once when sampling is done.
+    #[arg(long, value_name = "N")]
+    flush_every: Option<NonZeroUsize>,
+    /// Compress the output file, adding `.gz` or `.zst` to the default file name. Each needs the feature
+    /// of the same name.
+    #[arg(long, value_enum, default_value_t)]
+    compress: Compression,
+    /// With `--format json`, write a flat array of the samples without the run's metadata, like older
+    /// versions did.
+    #[arg(long)]
+    legacy_json: bool,
+    /// Repeat the run's `uuid` in every sample, for formats without metadata such as csv to tell runs apart.
+    /// Always done for sqlite and `--legacy-json`.
+    #[arg(long)]
+    per_row_uuid: bool,
+    /// With `--format markdown`, always write every sample instead of summarizing long runs.
+    #[arg(long)]
+    full: bool,
+    /// Keep only the peaks instead of every sample, for when the high-water marks are all that matter. The
+    /// output is just each run's metadata, with `peak_cpu`, `peak_mem`, `disk_read`, `disk_write` and
+    /// `exit_code`, and an empty `samples`. Only for json and yaml, and the peaks cover the whole tree with
+    /// `--tree`.
+    #[arg(long, conflicts_with_all = ["summary", "histogram", "bucket", "detect_leak", "fail_on_leak"])]
+    count_only: bool,
+    /// Show a live view of the latest sample on stderr while sampling.
+    #[arg(long, short)]
+    watch: bool,
+    /// Let the spawned program keep running after `peek` is interrupted with Ctrl-C.
+    #[arg(long)]
+    no_kill: bool,
+    /// Set an environment variable for the spawned program. Can be given multiple times.
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_env)]
+    env: Vec<(String, String)>,
+    /// Start the spawned program with an empty environment, before applying any `--env`.
+    #[arg(long)]
+    clear_env: bool,
+    /// Working directory to run the spawned program in.
+    #[arg(long)]
+    cwd: Option<PathBuf>,
+    /// Feed this file to the spawned program's stdin, instead of it inheriting `peek`'s.
+    #[arg(long)]
+    child_stdin: Option<PathBuf>,
+    /// Give the spawned program an empty stdin, for programs that would otherwise wait on the terminal.
+    #[arg(long, conflicts_with = "child_stdin")]
+    no_stdin: bool,
+    /// Write the spawned program's stdout to this file. When `peek` outputs to stdout the program's
+    /// stdout is discarded by default, to keep it from mixing into the data.
+    #[arg(long)]
+    child_stdout: Option<PathBuf>,
+    /// Write the spawned program's stderr to this file. With `--output stderr` the program's stderr is
+    /// discarded by default, like its stdout otherwise is.
+    #[arg(long)]
+    child_stderr: Option<PathBuf>,
+    /// Keep the spawned program's stdout and stderr in the metadata of the report, so one file holds
+    /// both its logs and its resource usage. Neither is shown while it runs.
+    #[arg(long, conflicts_with_all = ["child_stdout", "child_stderr"])]
+    capture: bool,
+    /// Bytes of stdout and of stderr to keep with `--capture`, like `64K`. The rest is discarded.
+    #[arg(long, value_name = "BYTES", value_parser = parse_bytes, default_value = "1M", requires = "capture")]
+    capture_limit: u64,
+    /// Send each sample as it is taken to `statsd://HOST:PORT`, or to an InfluxDB write url taking line
+    /// protocol, authenticating with `INFLUX_TOKEN` if set. Failed pushes don't stop sampling, and a push to
+    /// InfluxDB is given up on after a second.
+    #[cfg(feature = "push")]
+    #[arg(long, value_name = "URL")]
+    push: Option<String>,
+    /// Sample on another machine over `ssh`, running the `peek` installed there with the same options and
+    /// writing what it sends back here as `--output`, `--path` and `--format` say. Interrupting `peek` drops
+    /// the connection, which the remote side only notices next time it writes.
+    #[cfg(feature = "remote")]
+    #[arg(long, value_name = "USER@HOST", conflicts_with_all = ["config", "output_dir", "append"])]
+    remote: Option<String>,
+    /// Don't print any warnings. Errors are still reported.
+    #[arg(long, short, conflicts_with_all = ["verbose", "summary", "histogram", "watch"])]
+    quiet: bool,
+    /// Print what `peek` is doing to stderr, including every sample as it is taken.
+    #[arg(long, short)]
+    verbose: bool,
+    /// Load options from a TOML file. Options given on the command line take precedence.
+    #[arg(long, short)]
+    config: Option<PathBuf>,
+    /// Print the JSON Schema of the json output of a single run and exit. Several runs are an array of it.
+    #[arg(long, exclusive = true)]
+    print_schema: bool,
+    /// Check the program can be found and the output written, print what would be sampled and exit
+    /// without running anything.
+    #[arg(long)]
+    dry_run: bool,
+    /// Run and sample the program without writing anything, for using `peek` only as a gate on
+    /// `--fail-if-cpu-over`, `--fail-if-mem-over` or `--fail-on-leak` and the program's exit code, or for
+    /// just the `--histogram`.
+    #[arg(
+        long,
+        conflicts_with_all = ["path", "output", "format", "append", "compress", "summary", "count_only"]
+    )]
+    no_output: bool,
+}
+
+#[derive(clap::Subcommand)]
+enum Subcommand {
+    Compare(compare::Args),
+    Replay(replay::Args),
+    Top(top::Args),
+}
+
+/// The wait until the next sample with `--adaptive`, doubling while `cpu` and `mem` hold steady.
+fn adapt(
+    wait: Duration,
+    last: Option<(f32, u64)>,
+    (cpu, mem): (f32, u64),
+    min: Duration,
+    max: Duration,
+) -> Duration {
+    let Some((last_cpu, last_mem)) = last else {
+        return min;
+    };
+
+    if steady((last_cpu, last_mem), (cpu, mem), 5.0) {
+        (wait * 2).min(max)
+    } else {
+        min
+    }
+}
+
+/// Whether `cpu` moved by under `tolerance` points and `mem` by at most `tolerance` percent since `last`.
+fn steady((last_cpu, last_mem): (f32, u64), (cpu, mem): (f32, u64), tolerance: f32) -> bool {
+    (cpu - last_cpu).abs() < tolerance
+        && mem.abs_diff(last_mem) as f64 <= last_mem as f64 * f64::from(tolerance) / 100.0
+}
+
+/// Whether `--on-change` leaves out a sample of `cpu` and `mem` after `last` was written. With `min_mem_delta`
+/// only `mem` moving by that many bytes gets it written.
+fn unchanged(
+    last: (f32, u64),
+    recorded: (f32, u64),
+    tolerance: f32,
+    min_mem_delta: Option<u64>,
+) -> bool {
+    match min_mem_delta {
+        Some(delta) => recorded.1.abs_diff(last.1) < delta,
+        None => steady(last, recorded, tolerance),
+    }
+}
+
+/// The pid of the one process named exactly `name`, bailing when there's none or several.
+fn pid_by_name(system: &System, name: &str) -> Result<usize> {
+    let mut pids: Vec<usize> = system
+        .processes()
+        .values()
+        .filter(|process| process.name() == name)
+        .map(|process| usize::from(process.pid()))
+        .collect();
+
+    pids.sort_unstable();
+
+    match pids.as_slice() {
+        [] => anyhow::bail!("no running process is named `{name}`"),
+        [pid] => Ok(*pid),
+        pids => anyhow::bail!(
+            "{} processes are named `{name}`, pick one with `--pid`: {}",
+            pids.len(),
+            pids.iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Replaces anything but letters, digits, `-`, `_` and `.` in `name`, for using it as a file name.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Checks `path` can be written to without truncating it, or leaving it behind if it didn't exist.
+fn writable(path: &Path) -> Result<()> {
+    let existed = path.exists();
+
+    File::options()
+        .append(true)
+        .create(true)
+        .open(path)
+        .with_context(|| format!("`{}` can't be written to", path.display()))?;
+
+    if !existed {
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+fn parse_bytes(bytes: &str) -> Result<u64> {
+    let (number, multiplier) = match bytes.trim_end_matches(['B', 'b', 'i']) {
+        number if number.ends_with(['K', 'k']) => (&number[..number.len() - 1], 1 << 10),
+        number if number.ends_with(['M', 'm']) => (&number[..number.len() - 1], 1 << 20),
+        number if number.ends_with(['G', 'g']) => (&number[..number.len() - 1], 1 << 30),
+        number => (number, 1),
+    };
+
+    let number: u64 = number
+        .trim()
+        .parse()
+        .with_context(|| format!("expected a number of bytes like `512M` but got `{bytes}`"))?;
+
+    number
+        .checked_mul(multiplier)
+        .with_context(|| format!("`{bytes}` is more bytes than fit in 64 bits"))
+}
+
+fn parse_env(env: &str) -> Result<(String, String)> {
+    let (key, value) = env
+        .split_once('=')
+        .with_context(|| format!("expected `KEY=VALUE` but no `=` was found in `{env}`"))?;
+
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+/// How much `peek` reports about itself on stderr.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+enum CpuScale {
+    /// 100% is one core fully in use, like `top` reports. Exceeds 100% when using several cores.
+    #[default]
+    Core,
+    /// 100% is every core on the system fully in use.
+    System,
+}
+
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+enum MemUnit {
+    #[default]
+    Bytes,
+    /// Kibibytes, 1024 bytes.
+    Kib,
+    /// Mebibytes, 1024 KiB.
+    Mib,
+    /// Gibibytes, 1024 MiB.
+    Gib,
+}
+
+impl MemUnit {
+    fn convert(self, bytes: u64) -> f64 {
+        bytes as f64 / self.divisor() as f64
+    }
+
+    /// Turns a value in this unit back into bytes.
+    fn to_bytes(self, value: f64) -> u64 {
+        (value * self.divisor() as f64).round() as u64
+    }
+
+    fn divisor(self) -> u64 {
+        match self {
+            Self::Bytes => 1,
+            Self::Kib => 1 << 10,
+            Self::Mib => 1 << 20,
+            Self::Gib => 1 << 30,
+        }
+    }
+
+    /// Short name to print next to a value, e.g. `MiB`.
+    fn suffix(self) -> &'static str {
+        match self {
+            Self::Bytes => "B",
+            Self::Kib => "KiB",
+            Self::Mib => "MiB",
+            Self::Gib => "GiB",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Output {
+    File,
+    Stdout,
+    /// Writes to stderr instead, leaving stdout to the program, whose own stderr goes to `/dev/null` unless
+    /// `--child-stderr` is given. Warnings are left out as with `--quiet` so they don't end up in the
+    /// output, and `--summary`, `--histogram` and leak detection can't be used. Errors, like those of the
+    /// `--fail-if-*` limits, still go there, after the data and a blank line.
+    Stderr,
+    /// Writes the file and prints the same to stdout.
+    Both,
+}
+
+impl Output {
+    /// Whether this writes to `--path`.
+    fn writes_file(self) -> bool {
+        matches!(self, Self::File | Self::Both)
+    }
+}
+
+/// Writes everything to the output file and stdout alike, for `--output both`.
+struct Tee(Box<dyn Write>, std::io::Stdout);
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write_all(buf)?;
+        self.1.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()?;
+        self.1.flush()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    Csv,
+    /// Tab separated values, otherwise like `csv`.
+    Tsv,
+    Json,
+    /// The same structure as `json`, as YAML.
+    Yaml,
+    /// Newline delimited JSON, written as each sample is taken.
+    Ndjson,
+    /// Self-contained report with an interactive chart of CPU and memory.
+    Html,
+    /// GitHub flavored markdown table of the samples, or of a summary once there are more than 50.
+    Markdown,
+    /// Snappy compressed Parquet, for loading into pandas or polars. Needs the `parquet` feature.
+    Parquet,
+    /// The same structure as `json`, as MessagePack, far smaller for long runs. `peek replay` turns it back
+    /// into any other format. Needs the `msgpack` feature.
+    Msgpack,
+    /// Prometheus text exposition format of the latest sample, for node_exporter's textfile collector.
+    Prometheus,
+    /// Appends to a SQLite database, with a `runs` table and a `samples` table keyed by the run's `uuid`.
+    /// Needs the `sqlite` feature.
+    Sqlite,
+    /// Picked from the extension of the output path, like `csv` for `peek.csv` or `peek.csv.gz`. Falls back
+    /// to `json` when there's no path, or with a warning when the extension isn't one of the above.
+    Auto,
+}
+
+impl Format {
+    /// The format a file with the extension of `path` holds, looking past a compression extension.
+    fn infer(path: &Path) -> Option<Self> {
+        let mut extension = path.extension()?.to_str()?;
+
+        if matches!(extension, "gz" | "zst") {
+            extension = Path::new(path.file_stem()?).extension()?.to_str()?;
+        }
+
+        let format = match extension.to_ascii_lowercase().as_str() {
+            "csv" => Self::Csv,
+            "tsv" => Self::Tsv,
+            "json" => Self::Json,
+            "yaml" | "yml" => Self::Yaml,
+            "ndjson" | "jsonl" => Self::Ndjson,
+            "html" | "htm" => Self::Html,
+            "md" | "markdown" => Self::Markdown,
+            "parquet" => Self::Parquet,
+            "msgpack" | "mpk" => Self::Msgpack,
+            "prom" => Self::Prometheus,
+            "db" | "sqlite" | "sqlite3" => Self::Sqlite,
+            _ => return None,
+        };
+
+        Some(format)
+    }
+
+    /// Replaces `auto` with the format inferred from `path`.
+    fn resolve(self, path: Option<&Path>) -> Self {
+        match self {
+            Self::Auto => path.and_then(Self::infer).unwrap_or(Self::Json),
+            format => format,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+enum SampleLimitAction {
+    /// Stop sampling and write what was collected, leaving the program running.
+    #[default]
+    KeepRunning,
+    /// Stop sampling and terminate the program like Ctrl-C would, recording its exit code. An attached
+    /// process is left running.
+    Stop,
+    /// Keep sampling until the program exits, writing only the last `--max-samples` samples, which keep
+    /// their `sample` numbers. The metadata's peaks still cover the whole run, the summary and leak
+    /// detection only the samples kept. Not for ndjson, which is written as it's sampled.
+    Ring,
+}
+
+#[derive(Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+enum TimeFormat {
+    /// Milliseconds since the Unix epoch.
+    #[default]
+    EpochMs,
+    /// Seconds since the Unix epoch, with milliseconds as the fraction.
+    EpochS,
+    /// Date and time in UTC, like `2024-05-01T12:30:00.250Z`.
+    Rfc3339,
+    /// Seconds since the run started, easiest to plot.
+    Relative,
+}
+
+impl TimeFormat {
+    /// `timestamp` in this format, `start` being when its run started for `relative`.
+    fn format(self, timestamp: u64, start: u64) -> Value {
+        match self {
+            Self::EpochMs => Value::from(timestamp),
+            Self::EpochS => Value::from(timestamp as f64 / 1000.0),
+            Self::Rfc3339 => Value::from(rfc3339(timestamp)),
+            Self::Relative => Value::from(timestamp.saturating_sub(start) as f64 / 1000.0),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            Self::Csv => "csv",
+            Self::Tsv => "tsv",
+            Self::Json => "json",
+            Self::Yaml => "yaml",
+            Self::Ndjson => "ndjson",
+            Self::Html => "html",
+            Self::Markdown => "md",
+            Self::Parquet => "parquet",
+            Self::Msgpack => "msgpack",
+            Self::Prometheus => "prom",
+            Self::Sqlite => "db",
+            Self::Auto => "auto",
+        };
+
+        write!(f, "{}", str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("peek").chain(args.iter().copied()))
+            .expect("arguments are valid")
+    }
+
+    fn command_lines(args: &[&str]) -> Vec<Vec<String>> {
+        programs(&cli(args))
+            .expect("programs parse")
+            .into_iter()
+            .map(|(command, _)| command)
+            .collect()
+    }
+
+    #[test]
+    fn lone_program_keeps_single_quoted_arguments_together() {
+        assert_eq!(
+            command_lines(&["grep foo 'my file.txt'"]),
+            [["grep", "foo", "my file.txt"]]
+        );
+    }
+
+    #[test]
+    fn lone_program_keeps_double_quoted_arguments_together() {
+        assert_eq!(
+            command_lines(&[r#"grep "foo bar" file.txt"#]),
+            [["grep", "foo bar", "file.txt"]]
+        );
+    }
+
+    #[test]
+    fn lone_program_keeps_escaped_spaces() {
+        assert_eq!(
+            command_lines(&[r"cat my\ file.txt"]),
+            [["cat", "my file.txt"]]
+        );
+    }
+
+    #[test]
+    fn several_arguments_are_passed_as_is() {
+        let programs = programs(&cli(&["echo", "a b", "c"])).unwrap();
+
+        assert_eq!(programs[0].0, ["echo", "a b", "c"]);
+        assert_eq!(programs[0].1, "echo 'a b' c");
+    }
+
+    #[test]
+    fn unbalanced_quotes_fail_to_parse() {
+        let err = programs(&cli(&["grep 'foo"])).unwrap_err();
+
+        assert!(err.to_string().contains("failed to parse program"), "{err}");
+    }
+
+    fn program(command: &[&str], args: &[&str]) -> Program {
+        let command = command.iter().map(|arg| arg.to_string()).collect();
+        Program::new(command, &cli(args)).expect("the working directory exists")
+    }
+
+    #[test]
+    fn spawning_a_missing_binary_is_an_error() {
+        let Err(err) = program(&["peek-test-no-such-binary"], &["true"]).run() else {
+            panic!("a missing binary was spawned");
+        };
+
+        assert_eq!(
+            err.to_string(),
+            "command not found: `peek-test-no-such-binary`"
+        );
+    }
+
+    /// A new empty directory to put files in for a test.
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("peek-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    fn executable(dir: &Path, name: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join(name);
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path.canonicalize().unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_an_absolute_path() {
+        let tool = executable(&temp_dir(), "tool");
+        let resolved = program(&[tool.to_str().unwrap()], &["true"])
+            .resolve()
+            .unwrap();
+
+        assert_eq!(resolved, tool);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_a_relative_path_against_the_working_directory() {
+        let dir = temp_dir();
+        let tool = executable(&dir, "tool");
+        let resolved = program(&["./tool"], &["--cwd", dir.to_str().unwrap(), "true"])
+            .resolve()
+            .unwrap();
+
+        assert_eq!(resolved.canonicalize().unwrap(), tool);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_a_bare_name_on_the_path_the_program_gets() {
+        let tool = executable(&temp_dir(), "tool");
+        let path = format!("PATH={}", tool.parent().unwrap().display());
+        let resolved = program(&["tool"], &["--env", &path, "true"])
+            .resolve()
+            .unwrap();
+
+        assert_eq!(resolved, tool);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bare_name_is_not_looked_up_in_the_working_directory() {
+        let dir = temp_dir();
+        executable(&dir, "tool");
+        let cwd = dir.to_str().unwrap();
+        let err = program(
+            &["tool"],
+            &["--cwd", cwd, "--env", "PATH=/peek-test-nowhere", "true"],
+        )
+        .resolve()
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "command not found: `tool`");
+    }
+
+    #[test]
+    fn missing_output_directory_is_an_error() {
+        let path = temp_dir().join("results").join("peek.json");
+        let args = ["--output", "file", "--path", path.to_str().unwrap(), "true"];
+
+        let Err(err) = Peek::new(cli(&args), channel().1) else {
+            panic!("the output directory was missing");
+        };
+
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "directory `{}` doesn't exist, create it or pass `--mkdir`",
+                path.parent().unwrap().display()
+            )
+        );
+    }
+
+    #[test]
+    fn mkdir_creates_the_missing_output_directory() {
+        let path = temp_dir().join("results").join("peek.json");
+        let args = [
+            "--mkdir",
+            "--output",
+            "file",
+            "--path",
+            path.to_str().unwrap(),
+            "true",
+        ];
+
+        assert!(Peek::new(cli(&args), channel().1).is_ok());
+        assert!(path.parent().unwrap().is_dir());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn interrupt_stops_and_reaps_the_program() {
+        let (tx, rx) = channel();
+        let mut peek = Peek::new(cli(&["--no-output", "sleep", "987"]), rx).unwrap();
+
+        // Already waiting when sampling starts, like a Ctrl-C right after the program is spawned.
+        tx.send(()).unwrap();
+
+        let start = Instant::now();
+        peek.run().unwrap();
+
+        // Stopped by `SIGTERM` well before it would have exited, and not counted as crashing on its own.
+        assert!(start.elapsed() < Duration::from_secs(30));
+        assert_eq!(peek.runs[0].exit_code, None);
+        assert_eq!(peek.runs[0].signal, None);
+
+        // Reaped rather than left behind as a zombie.
+        let mut system = System::new();
+        system.refresh_processes_specifics(ProcessRefreshKind::new());
+
+        assert!(!system
+            .processes()
+            .values()
+            .any(|process| process.cmd() == ["sleep", "987"]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_gets_the_program_unsplit() {
+        assert_eq!(
+            command_lines(&["--shell", "grep 'a b' big.txt | wc -l"]),
+            [["sh", "-c", "grep 'a b' big.txt | wc -l"]]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_joins_several_arguments_without_quoting() {
+        let programs = programs(&cli(&["--shell", "echo", "hi", "|", "wc", "-c"])).unwrap();
+
+        assert_eq!(programs[0].0, ["sh", "-c", "echo hi | wc -c"]);
+        assert_eq!(programs[0].1, "echo hi | wc -c");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_applies_to_every_cmd() {
+        assert_eq!(
+            command_lines(&["--shell", "--cmd", "true && false", "--cmd", "ls > out"]),
+            [["sh", "-c", "true && false"], ["sh", "-c", "ls > out"]]
+        );
+    }
+
+    fn memory(bytes: u64, unit: MemUnit) -> String {
+        serde_json::to_string(&Memory { bytes, unit }).unwrap()
+    }
+
+    #[test]
+    fn memory_is_written_in_its_unit() {
+        assert_eq!(memory(1536, MemUnit::Bytes), "1536");
+        assert_eq!(memory(1536, MemUnit::Kib), "1.5");
+        assert_eq!(memory(3 << 20, MemUnit::Mib), "3.0");
+        assert_eq!(memory(1 << 29, MemUnit::Gib), "0.5");
+    }
+
+    #[test]
+    fn memory_in_a_unit_converts_back_to_bytes() {
+        assert_eq!(MemUnit::Kib.to_bytes(1.5), 1536);
+        assert_eq!(
+            MemUnit::Mib.to_bytes(MemUnit::Mib.convert(123_456_789)),
+            123_456_789
+        );
+        assert_eq!(MemUnit::Bytes.to_bytes(10.4), 10);
+    }
+
+    #[test]
+    fn max_samples_of_zero_is_rejected() {
+        let args = ["peek", "--max-samples", "0", "true"];
+
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    /// Samples `args` with a ring of 3, fast enough to wrap it.
+    #[cfg(unix)]
+    fn ring(args: &[&str]) -> Peek {
+        let ring = [
+            "--max-samples",
+            "3",
+            "--sample-limit-action",
+            "ring",
+            "--interval",
+            "50",
+        ];
+        let args: Vec<&str> = ring.iter().chain(args).copied().collect();
+        let mut peek = Peek::new(cli(&args), channel().1).unwrap();
+
+        peek.run().unwrap();
+        peek
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ring_keeps_the_last_samples_in_order() {
+        let peek = ring(&[
+            "--output",
+            "file",
+            "--path",
+            temp_dir().join("peek.json").to_str().unwrap(),
+            "sleep",
+            "0.5",
+        ]);
+        let run = &peek.runs[0];
+
+        assert!(run.collected > 3);
+        assert_eq!(run.samples.len(), 3);
+        assert!(run
+            .samples
+            .windows(2)
+            .all(|pair| pair[0].sample < pair[1].sample));
+        // Numbered from 0, so the last sampled is one less than how many were.
+        assert_eq!(run.samples[2].sample, run.collected - 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ring_with_no_output_keeps_nothing_to_put_in_order() {
+        let peek = ring(&["--no-output", "sleep", "0.5"]);
+
+        assert!(peek.runs[0].collected > 3);
+        assert!(peek.runs[0].samples.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ring_with_count_only_keeps_nothing_to_put_in_order() {
+        let path = temp_dir().join("peek.json");
+        let peek = ring(&[
+            "--count-only",
+            "--output",
+            "file",
+            "--path",
+            path.to_str().unwrap(),
+            "sleep",
+            "0.5",
+        ]);
+
+        assert!(peek.runs[0].collected > 3);
+        assert!(peek.runs[0].samples.is_empty());
+    }
+
+    /// Two samples, only the first with network counters, like a process whose `/proc/<pid>/net/dev`
+    /// stopped being readable.
+    fn network_then_none() -> Vec<Samples> {
+        let network = Network {
+            net_rx: 10,
+            net_tx: 20,
+            net_rx_rate: 1.0,
+            net_tx_rate: 2.0,
+        };
+
+        vec![
+            Samples {
+                network: Some(network),
+                ..Samples::default()
+            },
+            Samples {
+                sample: 1,
+                ..Samples::default()
+            },
+        ]
+    }
+
+    fn rendered(format: Format, samples: &[Samples]) -> String {
+        let metadata = Metadata::default();
+        let reports = [Report {
+            metadata: &metadata,
+            samples,
+        }];
+        let rendering = Rendering {
+            format,
+            mem_unit: MemUnit::Bytes,
+            full: true,
+            legacy_json: false,
+            csv_header: true,
+            columns: &[],
+            time_format: TimeFormat::EpochMs,
+            #[cfg(feature = "sqlite")]
+            output_path: Path::new("peek.db"),
+        };
+
+        let mut output = Vec::new();
+        render(&mut output, &reports, &rendering).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn keys_keep_a_field_only_later_rows_have_in_place() {
+        let rows = [vec!["a", "c"], vec!["a", "b", "c"], vec!["a", "c", "d"]]
+            .map(|row| row.into_iter().map(String::from).collect::<Vec<_>>());
+
+        assert_eq!(keys(rows.iter()), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn csv_leaves_fields_a_sample_lacks_empty() {
+        let csv = rendered(Format::Csv, &network_then_none());
+        let lines: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+        let rx = lines[0].iter().position(|&key| key == "net_rx").unwrap();
+
+        assert!(lines.iter().all(|line| line.len() == lines[0].len()));
+        assert_eq!(lines[1][rx], "10");
+        assert_eq!(lines[2][rx], "");
+    }
+
+    #[test]
+    fn csv_starts_with_a_header_of_the_fields() {
+        let csv = rendered(Format::Csv, &[Samples::default()]);
+
+        assert_eq!(
+            csv.lines().next().unwrap(),
+            "label,sample,timestamp,delta_ms,pid,name,process_count,cpu,mem,virt_mem,threads,open_files,\
+             disk_read,disk_write,disk_read_rate,disk_write_rate"
+        );
+        assert_eq!(csv.lines().count(), 2);
+    }
+
+    #[test]
+    fn csv_quotes_names_with_commas_and_quotes() {
+        let sample = Samples {
+            name: r#"my, "odd" name"#.to_owned(),
+            label: "plain".to_owned(),
+            ..Samples::default()
+        };
+        let csv = rendered(Format::Csv, &[sample]);
+        let row = csv.lines().nth(1).unwrap();
+
+        assert!(
+            row.starts_with(r#"plain,0,0,0,,"my, ""odd"" name",0,"#),
+            "{row}"
+        );
+
+        let record = csv::Reader::from_reader(csv.as_bytes())
+            .records()
+            .next()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(&record[5], r#"my, "odd" name"#);
+    }
+
+    #[test]
+    fn markdown_shows_fields_a_sample_lacks_as_missing() {
+        let markdown = rendered(Format::Markdown, &network_then_none());
+        let lines: Vec<Vec<&str>> = markdown
+            .lines()
+            .filter(|line| line.starts_with('|'))
+            .map(|line| line.split('|').map(str::trim).collect())
+            .collect();
+        let rx = lines[0].iter().position(|&key| key == "net_rx").unwrap();
+
+        assert!(lines.iter().all(|line| line.len() == lines[0].len()));
+        assert_eq!(lines[2][rx], "10");
+        assert_eq!(lines[3][rx], "-");
+    }
+
+    #[test]
+    fn on_change_leaves_out_steady_samples() {
+        assert!(unchanged((10.0, 1000), (12.0, 1040), 5.0, None));
+        assert!(!unchanged((10.0, 1000), (16.0, 1000), 5.0, None));
+        assert!(!unchanged((10.0, 1000), (10.0, 1060), 5.0, None));
+    }
+
+    #[test]
+    fn min_mem_delta_only_writes_large_enough_mem_changes() {
+        const MIB: u64 = 1024 * 1024;
+
+        // Growing 2 MiB on 200 MiB, under the 5% `--on-change` tolerance but over the delta.
+        assert!(!unchanged(
+            (10.0, 200 * MIB),
+            (10.0, 202 * MIB),
+            5.0,
+            Some(MIB)
+        ));
+        assert!(!unchanged(
+            (10.0, 202 * MIB),
+            (10.0, 200 * MIB),
+            5.0,
+            Some(MIB)
+        ));
+        // CPU moving alone doesn't get one written.
+        assert!(unchanged(
+            (10.0, 200 * MIB),
+            (90.0, 200 * MIB),
+            5.0,
+            Some(MIB)
+        ));
+        assert!(unchanged(
+            (10.0, 200 * MIB),
+            (10.0, 200 * MIB + MIB / 2),
+            5.0,
+            Some(MIB)
+        ));
+    }
+
+    #[test]
+    fn unreadable_counters_are_absent() {
+        let row = row(Samples::default()).unwrap();
+
+        assert!(!row.contains_key("ctx_switches"));
+        assert!(!row.contains_key("page_faults"));
+    }
+
+    #[test]
+    fn unreadable_swap_is_absent() {
+        assert!(!row(Samples::default()).unwrap().contains_key("swap"));
+    }
+
+    #[test]
+    fn cgroup_limit_is_of_resident_memory() {
+        let limit = MemLimit::new(procfs::MemLimit::Cgroup(1000), 870, 4000, MemUnit::Bytes);
+
+        assert_eq!(limit.mem_limit.bytes, 1000);
+        assert_eq!(limit.mem_limit_pct, 87.0);
+    }
+
+    #[test]
+    fn address_space_limit_is_of_virtual_memory() {
+        let limit = MemLimit::new(
+            procfs::MemLimit::AddressSpace(8000),
+            870,
+            4000,
+            MemUnit::Bytes,
+        );
+
+        assert_eq!(limit.mem_limit.bytes, 8000);
+        assert_eq!(limit.mem_limit_pct, 50.0);
+    }
+
+    #[test]
+    fn parses_bytes_with_a_suffix() {
+        assert_eq!(parse_bytes("512").unwrap(), 512);
+        assert_eq!(parse_bytes("4K").unwrap(), 4 << 10);
+        assert_eq!(parse_bytes("512M").unwrap(), 512 << 20);
+        assert_eq!(parse_bytes("2GiB").unwrap(), 2 << 30);
+        assert_eq!(parse_bytes("3mb").unwrap(), 3 << 20);
+    }
+
+    #[test]
+    fn bytes_that_are_not_a_number_fail_to_parse() {
+        let err = parse_bytes("lots").unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "expected a number of bytes like `512M` but got `lots`"
+        );
+        assert!(parse_bytes("1.5G").is_err());
+        assert!(parse_bytes("-1M").is_err());
+    }
+
+    #[test]
+    fn bytes_over_64_bits_fail_to_parse() {
+        assert!(parse_bytes("99999999999G").is_err());
+    }
+
+    #[test]
+    fn steady_is_within_the_tolerance() {
+        // `mem` may move by the tolerance itself, `cpu` only by less.
+        assert!(steady((10.0, 1000), (14.9, 1050), 5.0));
+        assert!(!steady((10.0, 1000), (15.0, 1000), 5.0));
+        assert!(!steady((10.0, 1000), (10.0, 1051), 5.0));
+        assert!(steady((10.0, 1000), (5.5, 950), 5.0));
+    }
+
+    #[test]
+    fn adaptive_wait_doubles_while_steady_up_to_the_max() {
+        let (min, max) = (Duration::from_millis(200), Duration::from_millis(500));
+        let steady = Some((10.0, 1000));
+
+        let wait = adapt(min, steady, (11.0, 1010), min, max);
+        assert_eq!(wait, Duration::from_millis(400));
+
+        let wait = adapt(wait, steady, (11.0, 1010), min, max);
+        assert_eq!(wait, max);
+    }
+
+    #[test]
+    fn adaptive_wait_drops_back_on_a_change() {
+        let (min, max) = (Duration::from_millis(200), Duration::from_secs(5));
+        let wait = Duration::from_secs(3);
+
+        assert_eq!(adapt(wait, Some((10.0, 1000)), (40.0, 1000), min, max), min);
+        assert_eq!(adapt(wait, Some((10.0, 1000)), (10.0, 2000), min, max), min);
+        assert_eq!(adapt(wait, None, (10.0, 1000), min, max), min);
+    }
+
+    fn inferred(path: &str) -> Option<Format> {
+        Format::infer(Path::new(path))
+    }
+
+    #[test]
+    fn format_is_inferred_from_the_extension() {
+        assert!(inferred("peek.csv") == Some(Format::Csv));
+        assert!(inferred("results/peek.YML") == Some(Format::Yaml));
+        assert!(inferred("peek.jsonl") == Some(Format::Ndjson));
+        assert!(inferred("peek.md") == Some(Format::Markdown));
+    }
+
+    #[test]
+    fn format_is_inferred_past_a_compression_extension() {
+        assert!(inferred("peek.csv.gz") == Some(Format::Csv));
+        assert!(inferred("peek.ndjson.zst") == Some(Format::Ndjson));
+        assert!(inferred("peek.gz").is_none());
+    }
+
+    #[test]
+    fn auto_falls_back_to_json() {
+        assert!(inferred("peek").is_none());
+        assert!(inferred("peek.txt").is_none());
+        assert!(Format::Auto.resolve(Some(Path::new("peek.txt"))) == Format::Json);
+        assert!(Format::Auto.resolve(None) == Format::Json);
+        assert!(Format::Csv.resolve(Some(Path::new("peek.json"))) == Format::Csv);
+    }
+
+    #[test]
+    fn rfc3339_of_the_epoch() {
+        assert_eq!(rfc3339(0), "1970-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn rfc3339_keeps_the_milliseconds() {
+        assert_eq!(rfc3339(1_714_566_600_250), "2024-05-01T12:30:00.250Z");
+    }
+
+    #[test]
+    fn rfc3339_around_leap_days() {
+        assert_eq!(rfc3339(1_709_251_199_999), "2024-02-29T23:59:59.999Z");
+        assert_eq!(rfc3339(951_782_400_000), "2000-02-29T00:00:00.000Z");
+        // Not a leap year, as divisible by 100 but not by 400.
+        assert_eq!(rfc3339(4_107_542_400_000), "2100-03-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn timestamps_in_each_time_format() {
+        let (timestamp, start) = (1_714_566_600_250, 1_714_566_598_000);
+
+        assert_eq!(
+            TimeFormat::EpochMs.format(timestamp, start),
+            1_714_566_600_250_u64
+        );
+        assert_eq!(
+            TimeFormat::EpochS.format(timestamp, start),
+            1_714_566_600.25
+        );
+        assert_eq!(TimeFormat::Relative.format(timestamp, start), 2.25);
+        // A sample stamped before its run started, by the wall clock stepping back.
+        assert_eq!(TimeFormat::Relative.format(start - 1, start), 0.0);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn shell_is_cmd_on_windows() {
+        assert_eq!(shell("dir | find \"x\""), ["cmd", "/C", "dir | find \"x\""]);
     }
 }
@@ -0,0 +1,162 @@
+use crate::{keys, row, MemUnit, Report};
+use anyhow::Result;
+use serde_json::Value;
+use std::fmt::Write;
+
+/// Runs with more samples than this get a summary table instead, unless `--full` is given.
+const MAX_ROWS: usize = 50;
+
+/// Renders a GitHub flavored markdown table for each run, ready to paste into an issue or pull
+/// request.
+///
+/// Short runs get a row per sample, longer ones a summary of each metric so the table stays readable.
+pub fn render(reports: &[Report], unit: MemUnit, full: bool) -> Result<String> {
+    let mut output = String::new();
+
+    for (i, report) in reports.iter().enumerate() {
+        if i > 0 {
+            output.push('\n');
+        }
+
+        // Writing to a `String` can't fail.
+        let _ = writeln!(output, "### {}\n", escape(&report.metadata.label));
+
+        if full || report.samples.len() <= MAX_ROWS {
+            samples(&mut output, report)?;
+        } else {
+            summary(&mut output, report, unit);
+        }
+    }
+
+    Ok(output)
+}
+
+fn samples(output: &mut String, report: &Report) -> Result<()> {
+    let rows = report.samples.iter().map(row).collect::<Result<Vec<_>>>()?;
+
+    if rows.is_empty() {
+        output.push_str("No samples were taken.\n");
+        return Ok(());
+    }
+
+    let keys = keys(rows.iter().map(|row| row.keys()));
+
+    table_row(output, keys.iter().cloned());
+    table_row(output, keys.iter().map(|_| "---".to_owned()));
+
+    for row in rows {
+        // Shown like a `null` for a field this sample doesn't have.
+        table_row(
+            output,
+            keys.iter().map(|key| match row.get(key) {
+                None | Some(Value::Null) => "-".to_owned(),
+                Some(Value::String(string)) => escape(string),
+                Some(value) => value.to_string(),
+            }),
+        );
+    }
+
+    Ok(())
+}
+
+fn summary(output: &mut String, report: &Report, unit: MemUnit) {
+    let _ = writeln!(
+        output,
+        "Summary of {} samples, use `--full` for all of them.\n",
+        report.samples.len()
+    );
+
+    table_row(
+        output,
+        ["metric", "min", "max", "mean", "p95"].map(String::from),
+    );
+    table_row(
+        output,
+        ["---", "---:", "---:", "---:", "---:"].map(String::from),
+    );
+
+    for (name, stats, precision) in crate::summary::metrics(report.samples, unit) {
+        let values = match stats {
+            Some(stats) => [stats.min, stats.max, stats.mean, stats.p95]
+                .map(|value| format!("{value:.precision$}")),
+            None => ["-", "-", "-", "-"].map(String::from),
+        };
+
+        table_row(output, std::iter::once(name.to_owned()).chain(values));
+    }
+}
+
+fn table_row(output: &mut String, cells: impl IntoIterator<Item = String>) {
+    output.push('|');
+
+    for cell in cells {
+        let _ = write!(output, " {cell} |");
+    }
+
+    output.push('\n');
+}
+
+/// Escapes `|`, which would otherwise end the cell early.
+fn escape(value: &str) -> String {
+    value.replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Metadata, Samples};
+
+    fn rendered(count: usize, full: bool) -> String {
+        let metadata = Metadata {
+            label: "a|b".to_owned(),
+            ..Metadata::default()
+        };
+        let samples: Vec<Samples> = (0..count as u64)
+            .map(|sample| Samples {
+                sample,
+                ..Samples::default()
+            })
+            .collect();
+        let reports = [Report {
+            metadata: &metadata,
+            samples: &samples,
+        }];
+
+        render(&reports, MemUnit::Bytes, full).unwrap()
+    }
+
+    #[test]
+    fn short_runs_get_a_row_per_sample() {
+        let markdown = rendered(MAX_ROWS, false);
+
+        assert!(markdown.starts_with("### a\\|b\n\n| label | sample |"));
+        // The header, its separator and a row per sample.
+        assert_eq!(
+            markdown
+                .lines()
+                .filter(|line| line.starts_with('|'))
+                .count(),
+            MAX_ROWS + 2
+        );
+    }
+
+    #[test]
+    fn long_runs_get_a_summary_unless_full() {
+        let markdown = rendered(MAX_ROWS + 1, false);
+
+        assert!(markdown.contains("Summary of 51 samples, use `--full` for all of them."));
+        assert!(markdown.contains("| metric | min | max | mean | p95 |"));
+
+        let full = rendered(MAX_ROWS + 1, true);
+
+        assert_eq!(
+            full.lines().filter(|line| line.starts_with('|')).count(),
+            MAX_ROWS + 3
+        );
+    }
+
+    #[test]
+    fn run_without_samples_says_so() {
+        assert!(rendered(0, false).ends_with("No samples were taken.\n"));
+    }
+}
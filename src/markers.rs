@@ -0,0 +1,190 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+
+/// A point in the run marked from outside, like when a deploy started, to line the samples up against.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct Marker {
+    /// When the marker came in, in unix epoch milliseconds.
+    pub timestamp: u64,
+    /// The sample taken next after it.
+    pub sample: u64,
+    /// The line written to `--marker-file`, `null` for a marker sent as a signal.
+    pub label: Option<String>,
+}
+
+/// Lines appended to a file while sampling, like those of `--marker-file` and `--phase-file`.
+pub struct Tail {
+    file: BufReader<File>,
+    /// What's been read of a line not yet finished with a newline.
+    partial: String,
+}
+
+impl Tail {
+    /// Opens `path`, created if it doesn't exist, at its end. `what` names it in errors.
+    pub fn open(path: &Path, what: &str) -> Result<Self> {
+        let mut file = File::options()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .with_context(|| format!("failed to open {what} `{}`", path.display()))?;
+
+        // Only what's written from now on counts, not what's left from an earlier run.
+        file.seek(SeekFrom::End(0))?;
+
+        Ok(Self {
+            file: BufReader::new(file),
+            partial: String::new(),
+        })
+    }
+
+    /// Every whole line appended since the last call, trimmed.
+    pub fn lines(&mut self) -> Result<Vec<String>> {
+        let mut lines = Vec::new();
+
+        // Reading at the end returns nothing until more is appended.
+        while self.file.read_line(&mut self.partial)? > 0 {
+            if !self.partial.ends_with('\n') {
+                break;
+            }
+
+            lines.push(std::mem::take(&mut self.partial).trim().to_owned());
+        }
+
+        Ok(lines)
+    }
+}
+
+/// Picks up markers from `SIGUSR1` with `--markers` and from lines appended to `--marker-file`.
+pub struct Markers {
+    /// When each signal was caught, in unix epoch milliseconds.
+    signals: Option<Receiver<u64>>,
+    file: Option<Tail>,
+}
+
+impl Markers {
+    pub fn new(signal: bool, path: Option<&Path>) -> Result<Self> {
+        Ok(Self {
+            signals: signal.then(listen).transpose()?,
+            file: path
+                .map(|path| Tail::open(path, "marker file"))
+                .transpose()?,
+        })
+    }
+
+    /// Every marker that came in since the last call, numbered as coming before `sample`.
+    pub fn poll(&mut self, sample: u64) -> Result<Vec<Marker>> {
+        let mut markers: Vec<Marker> = self
+            .signals
+            .iter()
+            .flat_map(|signals| signals.try_iter())
+            .map(|timestamp| Marker {
+                timestamp,
+                sample,
+                label: None,
+            })
+            .collect();
+
+        if let Some(file) = &mut self.file {
+            for label in file.lines()? {
+                if !label.is_empty() {
+                    markers.push(Marker {
+                        timestamp: crate::unix_millis()?,
+                        sample,
+                        label: Some(label),
+                    });
+                }
+            }
+        }
+
+        Ok(markers)
+    }
+}
+
+/// Sends when each `SIGUSR1` is caught, from a thread that waits for them.
+#[cfg(unix)]
+fn listen() -> Result<Receiver<u64>> {
+    let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGUSR1])
+        .context("failed to listen for `SIGUSR1`")?;
+
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            // Stops listening once sampling is over and the receiver is dropped, or the clock is unreadable.
+            if crate::unix_millis()
+                .ok()
+                .is_none_or(|now| tx.send(now).is_err())
+            {
+                break;
+            }
+        }
+    });
+
+    Ok(rx)
+}
+
+#[cfg(not(unix))]
+fn listen() -> Result<Receiver<u64>> {
+    anyhow::bail!("`--markers` needs signals, which only Unix has, use `--marker-file` instead")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn marker_file() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("peek-test-{}.markers", uuid::Uuid::new_v4()))
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = File::options().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn tail_only_reads_what_is_appended_after_opening() {
+        let path = marker_file();
+        std::fs::write(&path, "left from before\n").unwrap();
+
+        let mut tail = Tail::open(&path, "marker file").unwrap();
+        assert!(tail.lines().unwrap().is_empty());
+
+        append(&path, "deploy\n  warmup  \n");
+        assert_eq!(tail.lines().unwrap(), ["deploy", "warmup"]);
+        assert!(tail.lines().unwrap().is_empty());
+    }
+
+    #[test]
+    fn tail_waits_for_a_line_to_be_finished() {
+        let path = marker_file();
+        let mut tail = Tail::open(&path, "marker file").unwrap();
+
+        append(&path, "dep");
+        assert!(tail.lines().unwrap().is_empty());
+
+        append(&path, "loy\n");
+        assert_eq!(tail.lines().unwrap(), ["deploy"]);
+    }
+
+    #[test]
+    fn markers_from_the_file_skip_blank_lines() {
+        let path = marker_file();
+        let mut markers = Markers::new(false, Some(&path)).unwrap();
+
+        append(&path, "start\n\nend\n");
+        let markers = markers.poll(7).unwrap();
+
+        let labels: Vec<_> = markers
+            .iter()
+            .map(|marker| marker.label.as_deref())
+            .collect();
+
+        assert_eq!(labels, [Some("start"), Some("end")]);
+        assert!(markers.iter().all(|marker| marker.sample == 7));
+    }
+}
@@ -0,0 +1,29 @@
+//! Reads and writes MessagePack. Only built with the `msgpack` feature.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Encodes `reports` in the shape `json` has, as a single report or an array of them, with fields by name
+/// rather than position so files stay readable as fields are added.
+///
+/// Written as human readable, so values like `uuid` are strings the same as in `json`.
+pub fn render(reports: &[impl Serialize]) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut serializer = rmp_serde::Serializer::new(&mut bytes)
+        .with_struct_map()
+        .with_human_readable();
+
+    match reports {
+        [report] => report.serialize(&mut serializer)?,
+        reports => reports.serialize(&mut serializer)?,
+    }
+
+    Ok(bytes)
+}
+
+/// Decodes what `render` wrote, for `peek replay`.
+pub fn decode(bytes: &[u8]) -> Result<Value> {
+    let mut deserializer = rmp_serde::Deserializer::new(bytes).with_human_readable();
+    Ok(Value::deserialize(&mut deserializer)?)
+}
@@ -0,0 +1,80 @@
+//! Writes the samples as a Parquet file. Only built with the `parquet` feature.
+
+use crate::{flatten, keys, row, Samples};
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::basic::Compression;
+use ::parquet::file::properties::WriterProperties;
+use anyhow::Result;
+use arrow_array::{ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{Field, Schema};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Encodes the samples into a Snappy compressed Parquet file, with a column for each field.
+///
+/// Column types are inferred from the values, lists and groups like `cpu_cores` are split into a column
+/// per element just like with CSV.
+pub fn render(samples: &[&Samples]) -> Result<Vec<u8>> {
+    let rows = samples
+        .iter()
+        .map(|sample| Ok(flatten(row(sample)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    // A field only some samples have is null in the others.
+    let columns = keys(rows.iter().map(|row| row.iter().map(|(key, _)| key)));
+
+    let mut fields = Vec::with_capacity(columns.len());
+    let mut arrays = Vec::with_capacity(columns.len());
+
+    for column in &columns {
+        let values: Vec<&Value> = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .find(|(key, _)| key == column)
+                    .map_or(&Value::Null, |(_, value)| value)
+            })
+            .collect();
+
+        let array = to_array(&values);
+        fields.push(Field::new(column, array.data_type().clone(), true));
+        arrays.push(array);
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+
+    let mut bytes = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut bytes, schema.clone(), Some(properties))?;
+
+    if !rows.is_empty() {
+        writer.write(&RecordBatch::try_new(schema, arrays)?)?;
+    }
+
+    writer.close()?;
+
+    Ok(bytes)
+}
+
+/// Picks the narrowest type every value of the column fits in, falling back to strings.
+fn to_array(values: &[&Value]) -> ArrayRef {
+    let all = |fits: fn(&Value) -> bool| values.iter().all(|value| value.is_null() || fits(value));
+
+    if all(Value::is_i64) {
+        Arc::new(Int64Array::from_iter(values.iter().map(|v| v.as_i64())))
+    } else if all(Value::is_number) {
+        Arc::new(Float64Array::from_iter(values.iter().map(|v| v.as_f64())))
+    } else if all(Value::is_boolean) {
+        Arc::new(BooleanArray::from_iter(values.iter().map(|v| v.as_bool())))
+    } else {
+        Arc::new(StringArray::from_iter(values.iter().map(
+            |value| match value {
+                Value::Null => None,
+                Value::String(string) => Some(string.clone()),
+                value => Some(value.to_string()),
+            },
+        )))
+    }
+}
@@ -0,0 +1,361 @@
+//! Per-process values `sysinfo` doesn't expose, read straight from `/proc`.
+//!
+//! Only Linux is supported, everything returns `None` on other platforms.
+
+/// Number of threads the process is running.
+pub fn threads(pid: usize) -> Option<u64> {
+    status_field(pid, "Threads:")
+}
+
+/// Bytes of the process swapped out. `/proc` reports it in KiB, so it's converted here.
+pub fn swap(pid: usize) -> Option<u64> {
+    Some(status_field(pid, "VmSwap:")? * 1024)
+}
+
+/// Number of open file descriptors, counted from the entries of `/proc/<pid>/fd`.
+///
+/// This lists a directory every sample, so it costs more than the other values for processes with
+/// many descriptors open. Reading another user's process needs the same permissions as `ls` would.
+#[cfg(target_os = "linux")]
+pub fn open_files(pid: usize) -> Option<u64> {
+    Some(std::fs::read_dir(format!("/proc/{pid}/fd")).ok()?.count() as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn open_files(_pid: usize) -> Option<u64> {
+    None
+}
+
+/// Times the process was switched out, either waiting on something or preempted by the scheduler.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct ContextSwitches {
+    pub voluntary: u64,
+    pub involuntary: u64,
+}
+
+impl std::ops::Add for ContextSwitches {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            voluntary: self.voluntary + other.voluntary,
+            involuntary: self.involuntary + other.involuntary,
+        }
+    }
+}
+
+/// Page faults resolved without disk I/O as `minor`, or by reading from disk as `major`.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct PageFaults {
+    pub minor: u64,
+    pub major: u64,
+}
+
+impl std::ops::Add for PageFaults {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            minor: self.minor + other.minor,
+            major: self.major + other.major,
+        }
+    }
+}
+
+/// Context switches since the process started.
+pub fn ctx_switches(pid: usize) -> Option<ContextSwitches> {
+    Some(ContextSwitches {
+        voluntary: status_field(pid, "voluntary_ctxt_switches:")?,
+        involuntary: status_field(pid, "nonvoluntary_ctxt_switches:")?,
+    })
+}
+
+/// Page faults since the process started, from `/proc/<pid>/stat`.
+pub fn page_faults(pid: usize) -> Option<PageFaults> {
+    // `minflt` is the 10th field and `majflt` the 12th.
+    let fields = stat_fields(pid)?;
+
+    Some(PageFaults {
+        minor: fields.get(7)?.parse().ok()?,
+        major: fields.get(9)?.parse().ok()?,
+    })
+}
+
+/// Scheduling priority of the process, from `/proc/<pid>/stat`.
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct Priority {
+    /// From `19`, the lowest priority, to `-20`, the highest.
+    pub nice: i64,
+    /// The kernel's view of it, `20 + nice` for normal processes and negative for realtime ones.
+    pub priority: i64,
+}
+
+/// Priority and nice value of the process.
+pub fn priority(pid: usize) -> Option<Priority> {
+    // `priority` is the 18th field and `nice` the 19th.
+    let fields = stat_fields(pid)?;
+
+    Some(Priority {
+        nice: fields.get(16)?.parse().ok()?,
+        priority: fields.get(15)?.parse().ok()?,
+    })
+}
+
+/// Bytes received and transmitted over every interface but loopback.
+#[derive(Debug, Copy, Clone)]
+pub struct NetDev {
+    pub rx: u64,
+    pub tx: u64,
+}
+
+/// Network traffic of the process' network namespace, from `/proc/<pid>/net/dev`.
+///
+/// Linux only counts traffic per interface, so this is everything in the namespace and not the process'
+/// own, unless it's alone in there like in a container. Readable for any process without root.
+#[cfg(target_os = "linux")]
+pub fn net_dev(pid: usize) -> Option<NetDev> {
+    let dev = std::fs::read_to_string(format!("/proc/{pid}/net/dev")).ok()?;
+
+    // After two header lines, each line is the interface name and then 8 receive and 8 transmit columns.
+    dev.lines()
+        .skip(2)
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(interface, _)| interface.trim() != "lo")
+        .map(|(_, columns)| {
+            let columns: Vec<&str> = columns.split_whitespace().collect();
+
+            Some(NetDev {
+                rx: columns.first()?.parse().ok()?,
+                tx: columns.get(8)?.parse().ok()?,
+            })
+        })
+        .try_fold(NetDev { rx: 0, tx: 0 }, |total, interface| {
+            let interface = interface?;
+
+            Some(NetDev {
+                rx: total.rx + interface.rx,
+                tx: total.tx + interface.tx,
+            })
+        })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn net_dev(_pid: usize) -> Option<NetDev> {
+    None
+}
+
+/// Memory use of the cgroup the process is in, as the kernel counts it towards OOM kills.
+#[derive(Debug, Copy, Clone)]
+pub struct CgroupMemory {
+    pub current: u64,
+    /// `None` when the cgroup has no limit.
+    pub max: Option<u64>,
+}
+
+/// Reads `memory.current` and `memory.max` for the process' cgroup.
+///
+/// Only cgroup v2 is supported, mounted at `/sys/fs/cgroup` as systemd and container runtimes do. Under v1
+/// or a hybrid setup this returns `None`.
+#[cfg(target_os = "linux")]
+pub fn cgroup_memory(pid: usize) -> Option<CgroupMemory> {
+    let cgroups = std::fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+
+    // Under v2 there's a single hierarchy, listed as `0::/path`.
+    let path = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+    let dir = std::path::Path::new("/sys/fs/cgroup").join(path.trim_start_matches('/'));
+
+    let read = |file: &str| std::fs::read_to_string(dir.join(file)).ok();
+
+    Some(CgroupMemory {
+        current: read("memory.current")?.trim().parse().ok()?,
+        max: read("memory.max")?.trim().parse().ok(),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn cgroup_memory(_pid: usize) -> Option<CgroupMemory> {
+    None
+}
+
+/// The most memory a process can get before being killed or refused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+// Only ever read on Linux.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub enum MemLimit {
+    /// Its cgroup's `memory.max`, which resident memory is charged against.
+    Cgroup(u64),
+    /// Its `RLIMIT_AS`, which caps the address space, so virtual memory rather than resident.
+    AddressSpace(u64),
+}
+
+/// The cgroup's `memory.max` of the process, or failing that its `RLIMIT_AS` from `/proc/<pid>/limits`.
+/// `None` when neither is set.
+#[cfg(target_os = "linux")]
+pub fn mem_limit(pid: usize) -> Option<MemLimit> {
+    if let Some(max) = cgroup_memory(pid).and_then(|memory| memory.max) {
+        return Some(MemLimit::Cgroup(max));
+    }
+
+    let limits = std::fs::read_to_string(format!("/proc/{pid}/limits")).ok()?;
+
+    // The soft limit is the first column after the name, `unlimited` when there's none.
+    limits
+        .lines()
+        .find_map(|line| line.strip_prefix("Max address space"))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+        .map(MemLimit::AddressSpace)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn mem_limit(_pid: usize) -> Option<MemLimit> {
+    None
+}
+
+/// Energy counter of a CPU package, from `/sys/class/powercap`.
+#[derive(Debug, Copy, Clone)]
+pub struct Energy {
+    pub microjoules: u64,
+    /// Where the counter wraps back around to 0.
+    pub max: u64,
+}
+
+impl Energy {
+    /// Microjoules used since `before`, counting a single wrap of the counter in between.
+    pub fn since(self, before: Self) -> u64 {
+        if self.microjoules >= before.microjoules {
+            self.microjoules - before.microjoules
+        } else {
+            self.max - before.microjoules + self.microjoules
+        }
+    }
+}
+
+/// Energy counters of every CPU package as RAPL reports them, for the whole system rather than any process.
+///
+/// Only the top level `intel-rapl:<N>` zones are read, their subzones like `core` and `dram` are already part
+/// of the package's. Reading them needs root on most systems since they could leak what's running, so this
+/// returns `None` without it, or on hardware without RAPL.
+#[cfg(target_os = "linux")]
+pub fn package_energy() -> Option<Vec<Energy>> {
+    let mut zones: Vec<_> = std::fs::read_dir("/sys/class/powercap")
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("intel-rapl:"))
+                .is_some_and(|zone| !zone.contains(':'))
+        })
+        .collect();
+
+    // Paired up with the same package from one read to the next.
+    zones.sort();
+
+    let read = |path: &std::path::Path| -> Option<u64> {
+        std::fs::read_to_string(path).ok()?.trim().parse().ok()
+    };
+
+    let energy: Option<Vec<Energy>> = zones
+        .iter()
+        .map(|zone| {
+            Some(Energy {
+                microjoules: read(&zone.join("energy_uj"))?,
+                max: read(&zone.join("max_energy_range_uj"))?,
+            })
+        })
+        .collect();
+
+    energy.filter(|energy| !energy.is_empty())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn package_energy() -> Option<Vec<Energy>> {
+    None
+}
+
+/// Splits `/proc/<pid>/stat` into its fields, starting from the 3rd.
+#[cfg(target_os = "linux")]
+fn stat_fields(pid: usize) -> Option<Vec<String>> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+
+    // The name is in parentheses and can itself contain spaces, so only split what comes after it.
+    Some(
+        stat.rsplit_once(')')?
+            .1
+            .split_whitespace()
+            .map(str::to_owned)
+            .collect(),
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+fn stat_fields(_pid: usize) -> Option<Vec<String>> {
+    None
+}
+
+/// Parses the numeric value of `field` out of `/proc/<pid>/status`.
+#[cfg(target_os = "linux")]
+fn status_field(pid: usize, field: &str) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(field))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn status_field(_pid: usize, _field: &str) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn threads_of_this_process() {
+        assert!(threads(std::process::id() as usize).is_some_and(|threads| threads >= 1));
+    }
+
+    #[test]
+    fn threads_of_no_process() {
+        assert_eq!(threads(usize::MAX), None);
+    }
+
+    #[test]
+    fn energy_since_an_earlier_reading() {
+        let before = Energy {
+            microjoules: 1_000,
+            max: 10_000,
+        };
+        let after = Energy {
+            microjoules: 4_500,
+            ..before
+        };
+
+        assert_eq!(after.since(before), 3_500);
+    }
+
+    #[test]
+    fn energy_since_counts_the_counter_wrapping() {
+        let before = Energy {
+            microjoules: 9_000,
+            max: 10_000,
+        };
+        let after = Energy {
+            microjoules: 500,
+            ..before
+        };
+
+        assert_eq!(after.since(before), 1_500);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{Report, Samples};
+use std::fmt::Write;
+
+/// A metric's name, type, help text and how to read it from a sample, if it's available.
+type Metric = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&Samples) -> Option<String>,
+);
+
+const METRICS: [Metric; 9] = [
+    ("peek_process_cpu", "gauge", "CPU usage in percent.", |s| {
+        Some(s.cpu.to_string())
+    }),
+    (
+        "peek_process_memory_bytes",
+        "gauge",
+        "Resident memory in bytes.",
+        |s| Some(s.mem.bytes.to_string()),
+    ),
+    (
+        "peek_process_virtual_memory_bytes",
+        "gauge",
+        "Virtual memory in bytes.",
+        |s| Some(s.virt_mem.bytes.to_string()),
+    ),
+    (
+        "peek_process_swap_bytes",
+        "gauge",
+        "Memory swapped out in bytes.",
+        |s| s.swap.map(|swap| swap.bytes.to_string()),
+    ),
+    (
+        "peek_process_disk_read_bytes_total",
+        "counter",
+        "Bytes read from disk.",
+        |s| Some(s.disk_read.to_string()),
+    ),
+    (
+        "peek_process_disk_written_bytes_total",
+        "counter",
+        "Bytes written to disk.",
+        |s| Some(s.disk_write.to_string()),
+    ),
+    (
+        "peek_process_count",
+        "gauge",
+        "Number of processes the metrics were summed over.",
+        |s| Some(s.process_count.to_string()),
+    ),
+    ("peek_process_threads", "gauge", "Number of threads.", |s| {
+        s.threads.map(|threads| threads.to_string())
+    }),
+    (
+        "peek_process_open_files",
+        "gauge",
+        "Number of open file descriptors.",
+        |s| s.open_files.map(|open_files| open_files.to_string()),
+    ),
+];
+
+/// Renders the latest sample of each run in the Prometheus text exposition format, for node_exporter's
+/// textfile collector to scrape.
+///
+/// Memory is always in bytes, as Prometheus expects base units, regardless of `--mem-unit`.
+pub fn render(reports: &[Report]) -> String {
+    let latest: Vec<&Samples> = reports
+        .iter()
+        .filter_map(|report| report.samples.last())
+        .collect();
+
+    let mut output = String::new();
+
+    for (name, kind, help, value) in METRICS {
+        let values: Vec<(&Samples, String)> = latest
+            .iter()
+            .filter_map(|sample| Some((*sample, value(sample)?)))
+            .collect();
+
+        if values.is_empty() {
+            continue;
+        }
+
+        // Writing to a `String` can't fail.
+        let _ = writeln!(output, "# HELP {name} {help}");
+        let _ = writeln!(output, "# TYPE {name} {kind}");
+
+        for (sample, value) in values {
+            // No timestamp, the textfile collector rejects metrics that have one.
+            let _ = writeln!(
+                output,
+                "{name}{{pid=\"{}\",name=\"{}\",label=\"{}\"}} {value}",
+                sample.pid.map(|pid| pid.to_string()).unwrap_or_default(),
+                escape(&sample.name),
+                escape(&sample.label)
+            );
+        }
+    }
+
+    output
+}
+
+/// Escapes a label value, where backslashes, quotes and newlines are special.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+
+    #[test]
+    fn only_the_latest_sample_of_each_run_is_rendered() {
+        let metadata = Metadata::default();
+        let samples = [0.5, 7.25].map(|cpu| Samples {
+            pid: Some(42),
+            name: "server".to_owned(),
+            label: "say \"hi\"".to_owned(),
+            cpu,
+            ..Samples::default()
+        });
+        let reports = [Report {
+            metadata: &metadata,
+            samples: &samples,
+        }];
+
+        let output = render(&reports);
+
+        assert!(output.starts_with(
+            "# HELP peek_process_cpu CPU usage in percent.\n\
+             # TYPE peek_process_cpu gauge\n\
+             peek_process_cpu{pid=\"42\",name=\"server\",label=\"say \\\"hi\\\"\"} 7.25\n"
+        ));
+        assert!(!output.contains(" 0.5\n"));
+    }
+
+    #[test]
+    fn metrics_no_sample_has_are_left_out() {
+        let metadata = Metadata::default();
+        let samples = [Samples::default()];
+        let reports = [Report {
+            metadata: &metadata,
+            samples: &samples,
+        }];
+
+        let output = render(&reports);
+
+        assert!(!output.contains("peek_process_swap_bytes"));
+        assert!(!output.contains("peek_process_threads"));
+        assert!(output.contains("peek_process_memory_bytes{"));
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("a\\b\"c\nd"), "a\\\\b\\\"c\\nd");
+    }
+}
@@ -0,0 +1,162 @@
+//! Sends each sample to a metrics endpoint as it is taken. Only built with the `push` feature.
+
+use crate::Samples;
+use anyhow::{Context, Result};
+use std::net::UdpSocket;
+use std::time::Duration;
+
+/// How long a push to InfluxDB may take before it's dropped, as sampling waits on it.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where samples are pushed to.
+pub enum Push {
+    /// `statsd://HOST:PORT`, sent as gauges over UDP.
+    Statsd(UdpSocket),
+    /// `http(s)://...`, an InfluxDB write endpoint taking line protocol. Authenticates with `INFLUX_TOKEN`
+    /// when set.
+    Influx {
+        agent: ureq::Agent,
+        url: String,
+        token: Option<String>,
+    },
+}
+
+impl Push {
+    pub fn new(url: &str) -> Result<Self> {
+        if let Some(address) = url.strip_prefix("statsd://") {
+            let socket = UdpSocket::bind("0.0.0.0:0")?;
+            socket
+                .connect(address)
+                .with_context(|| format!("failed to resolve statsd address `{address}`"))?;
+
+            return Ok(Self::Statsd(socket));
+        }
+
+        if url.starts_with("http://") || url.starts_with("https://") {
+            return Ok(Self::Influx {
+                agent: ureq::Agent::config_builder()
+                    .timeout_global(Some(TIMEOUT))
+                    .build()
+                    .into(),
+                url: url.to_owned(),
+                token: std::env::var("INFLUX_TOKEN").ok(),
+            });
+        }
+
+        anyhow::bail!("expected `--push` to be a `statsd://` or `http(s)://` url but got `{url}`")
+    }
+
+    /// Best-effort, a failure is returned for reporting but the sample is otherwise dropped.
+    pub fn send(&self, sample: &Samples) -> Result<()> {
+        match self {
+            Self::Statsd(socket) => {
+                let prefix = format!("peek.{}", metric_name(&sample.label));
+                let payload = [
+                    format!("{prefix}.cpu:{}|g", sample.cpu),
+                    format!("{prefix}.mem:{}|g", sample.mem.bytes),
+                    format!("{prefix}.virt_mem:{}|g", sample.virt_mem.bytes),
+                    format!("{prefix}.disk_read_rate:{}|g", sample.disk_read_rate),
+                    format!("{prefix}.disk_write_rate:{}|g", sample.disk_write_rate),
+                ]
+                .join("\n");
+
+                socket.send(payload.as_bytes())?;
+            }
+            Self::Influx { agent, url, token } => {
+                let mut request = agent.post(url);
+
+                if let Some(token) = token {
+                    request = request.header("Authorization", &format!("Token {token}"));
+                }
+
+                request.send(line(sample))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// `sample` as a point of line protocol.
+fn line(sample: &Samples) -> String {
+    let mut tags = format!(
+        "pid={}",
+        sample
+            .pid
+            .map_or_else(|| "none".to_owned(), |pid| pid.to_string())
+    );
+
+    // An empty tag value isn't valid line protocol, so it's left out instead.
+    for (key, value) in [("name", &sample.name), ("label", &sample.label)] {
+        if !value.is_empty() {
+            tags.push_str(&format!(",{key}={}", escape_tag(value)));
+        }
+    }
+
+    format!(
+        "peek,{tags} cpu={},mem={}i,virt_mem={}i,disk_read={}i,disk_write={}i {}",
+        sample.cpu,
+        sample.mem.bytes,
+        sample.virt_mem.bytes,
+        sample.disk_read,
+        sample.disk_write,
+        // Line protocol defaults to nanosecond precision.
+        u128::from(sample.timestamp) * 1_000_000,
+    )
+}
+
+/// StatsD splits names on `.` and doesn't allow `:` or `|`, so only keep the safe characters.
+fn metric_name(label: &str) -> String {
+    label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Escapes the characters line protocol treats as separators in tag values.
+fn escape_tag(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_has_the_sample_tagged_and_escaped() {
+        let sample = Samples {
+            pid: Some(42),
+            name: "my server".to_owned(),
+            label: "a,b=c".to_owned(),
+            cpu: 1.5,
+            timestamp: 2,
+            ..Samples::default()
+        };
+
+        assert_eq!(
+            line(&sample),
+            "peek,pid=42,name=my\\ server,label=a\\,b\\=c \
+             cpu=1.5,mem=0i,virt_mem=0i,disk_read=0i,disk_write=0i 2000000"
+        );
+    }
+
+    #[test]
+    fn line_leaves_out_empty_tags() {
+        assert!(line(&Samples::default()).starts_with("peek,pid=none cpu="));
+    }
+
+    #[test]
+    fn metric_name_keeps_only_safe_characters() {
+        assert_eq!(metric_name("cargo build.rs:1|x"), "cargo_build_rs_1_x");
+    }
+}
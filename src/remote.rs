@@ -0,0 +1,134 @@
+use crate::compress::Compression;
+use crate::{Cli, Format, Output, Tee};
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory};
+use std::fs::File;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Options that are about where the output ends up, handled on this side rather than passed along.
+const LOCAL: [&str; 6] = ["remote", "path", "output", "format", "compress", "mkdir"];
+
+/// Runs `peek` on `host` over `ssh` with the same options, writing what it samples to stdout there and
+/// from there to where `--output` says here. Returns the exit code of the remote `peek`.
+///
+/// Only the options given on the command line are passed along, rebuilt from `matches` so they reach the
+/// remote side as they were parsed. `peek` has to be on the `PATH` of the remote shell.
+pub fn run(host: &str, cli: &Cli, matches: &ArgMatches) -> Result<Option<i32>> {
+    // `ssh` would take it as one of its own options.
+    if host.starts_with('-') {
+        anyhow::bail!("`{host}` isn't a host to connect to");
+    }
+
+    let format = cli.format.resolve(cli.path.as_deref());
+
+    if matches!(format, Format::Sqlite | Format::Parquet) {
+        anyhow::bail!("`--remote` can't stream the `{format}` format back, pick a text one");
+    }
+
+    cli.compress.check()?;
+
+    if cli.compress != Compression::None && !cli.output.writes_file() {
+        anyhow::bail!("`--compress` needs `--output file` or `--output both`");
+    }
+
+    let mut command = vec!["peek".to_owned(), format!("--format={format}")];
+    command.extend(forwarded(matches));
+
+    let path = cli.path.clone().unwrap_or_else(|| {
+        let extension = cli.compress.extension();
+
+        std::env::current_dir()
+            .expect("couldn't get cwd")
+            .join(match extension {
+                Some(extension) => format!("peek.{format}.{extension}"),
+                None => format!("peek.{format}"),
+            })
+    });
+
+    let mut writer: Box<dyn Write> = match cli.output {
+        Output::Stdout => Box::new(std::io::stdout()),
+        Output::Stderr => Box::new(std::io::stderr()),
+        output => {
+            if let Some(parent) = path
+                .parent()
+                .filter(|parent| cli.mkdir && !parent.as_os_str().is_empty())
+            {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("failed to create `{}`", parent.display()))?;
+            }
+
+            let file = File::create(&path)
+                .with_context(|| format!("failed to create `{}`", path.display()))?;
+            let file = cli.compress.writer(file)?;
+
+            match output {
+                Output::Both => Box::new(Tee(file, std::io::stdout())),
+                Output::File | Output::Stdout | Output::Stderr => file,
+            }
+        }
+    };
+
+    // `ssh` hands the remote shell a single command line, so everything is quoted for it.
+    let mut ssh = Command::new("ssh")
+        .arg(host)
+        .arg(shell_words::join(&command))
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("failed to run `ssh`")?;
+
+    let mut stdout = ssh.stdout.take().expect("stdout is piped");
+
+    std::io::copy(&mut stdout, &mut writer).context("failed to write the remote output")?;
+    writer.flush()?;
+
+    let status = ssh.wait()?;
+
+    // What a shell exits with for a command it can't find.
+    if status.code() == Some(127) {
+        anyhow::bail!("couldn't run `peek` on `{host}`, is it installed there?");
+    }
+
+    Ok(status.code())
+}
+
+/// Every option given on the command line but those in `LOCAL`, followed by the program.
+fn forwarded(matches: &ArgMatches) -> Vec<String> {
+    let mut args = Vec::new();
+
+    for arg in Cli::command().get_arguments() {
+        let id = arg.get_id().as_str();
+
+        if LOCAL.contains(&id) || matches.value_source(id) != Some(ValueSource::CommandLine) {
+            continue;
+        }
+
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+
+        match arg.get_action() {
+            ArgAction::SetTrue => args.push(format!("--{long}")),
+            _ => {
+                for value in matches.get_raw(id).into_iter().flatten() {
+                    args.push(format!("--{long}={}", value.to_string_lossy()));
+                }
+            }
+        }
+    }
+
+    let program: Vec<String> = matches
+        .get_many::<String>("program")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect();
+
+    if !program.is_empty() {
+        args.push("--".to_owned());
+        args.extend(program);
+    }
+
+    args
+}
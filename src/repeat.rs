@@ -0,0 +1,98 @@
+use crate::{Memory, Peaks, Run, Samples, Spread};
+
+/// Folds every `repeat` runs in a row, the repeats of one program, into a single run of their mean, for
+/// `--average`.
+///
+/// Runs are lined up by sample index, so the `n`th sample is the mean of the `n`th sample of every run that
+/// got that far, with their standard deviation and how many there were. Everything not averaged, like
+/// `timestamp` and `pid`, is that of the first run to get that far.
+pub fn average(runs: Vec<Run>, repeat: usize, precision: Option<u32>) -> Vec<Run> {
+    let mut runs = runs.into_iter().peekable();
+    let mut averaged = Vec::new();
+
+    while runs.peek().is_some() {
+        averaged.push(fold(runs.by_ref().take(repeat).collect(), precision));
+    }
+
+    averaged
+}
+
+fn fold(group: Vec<Run>, precision: Option<u32>) -> Run {
+    let mut group = group.into_iter();
+    let mut run = group.next().expect("groups are never empty");
+
+    let mut samples = vec![std::mem::take(&mut run.samples)];
+
+    for mut other in group {
+        samples.push(std::mem::take(&mut other.samples));
+        run.events.append(&mut other.events);
+        run.markers.append(&mut other.markers);
+        run.collected += other.collected;
+        run.end_time = other.end_time.or(run.end_time);
+        run.exit_code = run.exit_code.filter(|&code| code != 0).or(other.exit_code);
+        run.signal = run.signal.or(other.signal);
+    }
+
+    let longest = samples.iter().map(Vec::len).max().unwrap_or_default();
+
+    run.samples = (0..longest)
+        .map(|index| {
+            let at: Vec<&Samples> = samples.iter().filter_map(|run| run.get(index)).collect();
+            let mut sample = mean(index as u64, &at);
+            sample.round(precision);
+            sample
+        })
+        .collect();
+
+    run.peaks = Peaks::default();
+
+    for sample in &run.samples {
+        run.peaks.update(sample);
+    }
+
+    run
+}
+
+/// The mean of `at`, samples taken at the same index of different runs.
+fn mean(index: u64, at: &[&Samples]) -> Samples {
+    let first = at[0];
+    let n = at.len() as f64;
+
+    let mean = |value: &dyn Fn(&Samples) -> f64| at.iter().map(|s| value(s)).sum::<f64>() / n;
+
+    // Of a sample rather than a population, as the runs are only a few of those that could be made.
+    let stddev = |value: &dyn Fn(&Samples) -> f64| {
+        if at.len() < 2 {
+            return 0.0;
+        }
+
+        let mean = mean(value);
+        (at.iter().map(|s| (value(s) - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+    };
+
+    let memory = |bytes: f64| Memory {
+        bytes: bytes.round() as u64,
+        unit: first.mem.unit,
+    };
+
+    Samples {
+        sample: index,
+        delta_ms: mean(&|s| s.delta_ms as f64).round() as u64,
+        cpu: mean(&|s| f64::from(s.cpu)) as f32,
+        cpu_smoothed: first
+            .cpu_smoothed
+            .map(|_| mean(&|s| f64::from(s.cpu_smoothed.unwrap_or_default())) as f32),
+        mem: memory(mean(&|s| s.mem.bytes as f64)),
+        virt_mem: memory(mean(&|s| s.virt_mem.bytes as f64)),
+        disk_read: mean(&|s| s.disk_read as f64).round() as u64,
+        disk_write: mean(&|s| s.disk_write as f64).round() as u64,
+        disk_read_rate: mean(&|s| s.disk_read_rate),
+        disk_write_rate: mean(&|s| s.disk_write_rate),
+        spread: Some(Spread {
+            cpu_stddev: stddev(&|s| f64::from(s.cpu)) as f32,
+            mem_stddev: memory(stddev(&|s| s.mem.bytes as f64)),
+            runs: at.len(),
+        }),
+        ..first.clone()
+    }
+}
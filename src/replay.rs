@@ -0,0 +1,383 @@
+use crate::{
+    check_columns, render, Format, MemUnit, Memory, Metadata, Peaks, Rendering, Report, Samples,
+    TimeFormat,
+};
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Write the JSON, NDJSON or MessagePack output of an earlier run out again in another format, without running
+/// anything.
+#[derive(clap::Args)]
+pub struct Args {
+    /// Output of `peek` in json, `--legacy-json`, ndjson or msgpack, which is told apart by its extension.
+    input: PathBuf,
+    /// Write to this file instead of stdout.
+    output: Option<PathBuf>,
+    #[arg(value_enum, long, short, default_value = "auto")]
+    format: Format,
+    /// Unit to write memory in. Defaults to that of the input.
+    #[arg(value_enum, long)]
+    mem_unit: Option<MemUnit>,
+    /// Unit of memory in ndjson and `--legacy-json` input, which don't record it.
+    #[arg(value_enum, long, default_value_t)]
+    input_mem_unit: MemUnit,
+    /// In markdown, list every sample instead of summarizing past 50 of them.
+    #[arg(long)]
+    full: bool,
+    /// Only write these fields of each sample, like `peek --columns`.
+    #[arg(long, value_delimiter = ',', value_name = "COLUMNS")]
+    columns: Vec<String>,
+    /// How each sample's `timestamp` is written, like `peek --time-format`.
+    #[arg(value_enum, long, default_value_t)]
+    time_format: TimeFormat,
+}
+
+/// Fields holding memory, converted to bytes before reading them in.
+const SAMPLE_MEMORY: [&str; 10] = [
+    "mem",
+    "virt_mem",
+    "swap",
+    "cgroup_mem",
+    "cgroup_mem_max",
+    "mem_limit",
+    "mem_stddev",
+    "system_mem_total",
+    "system_mem_used",
+    "system_mem_available",
+];
+const METADATA_MEMORY: [&str; 3] = ["peak_mem", "peak_virt_mem", "total_mem"];
+
+pub fn run(args: &Args) -> Result<()> {
+    if let Some(path) = args
+        .output
+        .as_deref()
+        .filter(|path| args.format == Format::Auto && Format::infer(path).is_none())
+    {
+        eprintln!(
+            "warning: couldn't tell the format from the extension of `{}`, writing json",
+            path.display()
+        );
+    }
+
+    let format = args.format.resolve(args.output.as_deref());
+
+    if cfg!(not(feature = "parquet")) && format == Format::Parquet {
+        anyhow::bail!("parquet output needs `peek` to be built with the `parquet` feature");
+    }
+
+    if cfg!(not(feature = "msgpack")) && format == Format::Msgpack {
+        anyhow::bail!("msgpack output needs `peek` to be built with the `msgpack` feature");
+    }
+
+    if cfg!(not(feature = "sqlite")) && format == Format::Sqlite {
+        anyhow::bail!("sqlite output needs `peek` to be built with the `sqlite` feature");
+    }
+
+    check_columns(&args.columns, args.time_format, format)?;
+
+    let mut runs = load(&args.input, args.input_mem_unit)?;
+
+    let unit = args
+        .mem_unit
+        .or_else(|| runs.first().map(|(metadata, _)| metadata.mem_unit))
+        .unwrap_or_default();
+
+    for (metadata, samples) in &mut runs {
+        metadata.mem_unit = unit;
+        metadata.peak_mem.unit = unit;
+        metadata.peak_virt_mem.unit = unit;
+        metadata.total_mem.unit = unit;
+
+        for sample in samples {
+            // The database joins samples to their run by it.
+            if format == Format::Sqlite {
+                sample.uuid = Some(metadata.uuid);
+            }
+
+            sample.mem.unit = unit;
+            sample.virt_mem.unit = unit;
+
+            if let Some(swap) = &mut sample.swap {
+                swap.unit = unit;
+            }
+
+            if let Some(cgroup) = &mut sample.cgroup {
+                cgroup.cgroup_mem.unit = unit;
+
+                if let Some(max) = &mut cgroup.cgroup_mem_max {
+                    max.unit = unit;
+                }
+            }
+
+            if let Some(limit) = &mut sample.limit {
+                limit.mem_limit.unit = unit;
+            }
+
+            if let Some(spread) = &mut sample.spread {
+                spread.mem_stddev.unit = unit;
+            }
+
+            if let Some(system) = &mut sample.system {
+                system.system_mem_total.unit = unit;
+                system.system_mem_used.unit = unit;
+                system.system_mem_available.unit = unit;
+            }
+        }
+    }
+
+    let reports: Vec<Report> = runs
+        .iter()
+        .map(|(metadata, samples)| Report { metadata, samples })
+        .collect();
+
+    let mut writer: Box<dyn Write> = match (&args.output, format) {
+        // Written through its own connection, creating the file here would truncate the database.
+        (Some(_), Format::Sqlite) => Box::new(std::io::sink()),
+        (None, Format::Sqlite) => {
+            anyhow::bail!("the sqlite format can only be written to a file, give an output path")
+        }
+        (Some(path), _) => {
+            Box::new(BufWriter::new(File::create(path).with_context(|| {
+                format!("failed to create `{}`", path.display())
+            })?))
+        }
+        (None, _) => Box::new(std::io::stdout()),
+    };
+
+    let rendering = Rendering {
+        format,
+        mem_unit: unit,
+        full: args.full,
+        legacy_json: false,
+        csv_header: true,
+        columns: &args.columns,
+        time_format: args.time_format,
+        #[cfg(feature = "sqlite")]
+        output_path: args.output.as_deref().unwrap_or(Path::new("")),
+    };
+
+    render(&mut writer, &reports, &rendering)?;
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads every run in `path`, with its memory values in bytes.
+pub fn load(path: &Path, input_unit: MemUnit) -> Result<Vec<(Metadata, Vec<Samples>)>> {
+    let file =
+        std::fs::read(path).with_context(|| format!("failed to read `{}`", path.display()))?;
+
+    let not_peek = || format!("`{}` is not the output of `peek`", path.display());
+
+    let parsed: serde_json::Result<Value> = match Format::infer(path) {
+        #[cfg(feature = "msgpack")]
+        Some(Format::Msgpack) => Ok(crate::msgpack::decode(&file).with_context(not_peek)?),
+        #[cfg(not(feature = "msgpack"))]
+        Some(Format::Msgpack) => {
+            anyhow::bail!("reading msgpack needs `peek` to be built with the `msgpack` feature")
+        }
+        _ => serde_json::from_slice(&file),
+    };
+
+    // Reports have their metadata, the samples of `--legacy-json` and ndjson have to go without.
+    let runs: Vec<(Option<Value>, Vec<Value>)> = match parsed {
+        Ok(Value::Object(report)) if report.contains_key("samples") => vec![split(report)],
+        Ok(Value::Array(reports)) if reports.iter().any(|run| run.get("metadata").is_some()) => {
+            reports
+                .into_iter()
+                .map(|report| match report {
+                    Value::Object(report) => Ok(split(report)),
+                    _ => anyhow::bail!(not_peek()),
+                })
+                .collect::<Result<_>>()?
+        }
+        Ok(Value::Array(samples)) => by_label(samples),
+        // A single line of ndjson.
+        Ok(sample @ Value::Object(_)) => by_label(vec![sample]),
+        Ok(_) => anyhow::bail!(not_peek()),
+        Err(_) => by_label(
+            std::str::from_utf8(&file)
+                .with_context(not_peek)?
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<serde_json::Result<_>>()
+                .with_context(not_peek)?,
+        ),
+    };
+
+    runs.into_iter()
+        .map(|(metadata, mut samples)| {
+            let unit = match &metadata {
+                Some(metadata) => {
+                    serde_json::from_value(metadata["mem_unit"].clone()).with_context(not_peek)?
+                }
+                None => input_unit,
+            };
+
+            for sample in &mut samples {
+                to_bytes(sample, &SAMPLE_MEMORY, unit);
+            }
+
+            let samples: Vec<Samples> = samples
+                .into_iter()
+                .map(serde_json::from_value)
+                .collect::<serde_json::Result<_>>()
+                .with_context(not_peek)?;
+
+            let metadata = match metadata {
+                Some(mut metadata) => {
+                    to_bytes(&mut metadata, &METADATA_MEMORY, unit);
+                    serde_json::from_value(metadata).with_context(not_peek)?
+                }
+                None => recover(&samples),
+            };
+
+            Ok((metadata, samples))
+        })
+        .collect()
+}
+
+fn split(mut report: Map<String, Value>) -> (Option<Value>, Vec<Value>) {
+    let samples = match report.remove("samples") {
+        Some(Value::Array(samples)) => samples,
+        _ => Vec::new(),
+    };
+
+    (report.remove("metadata"), samples)
+}
+
+/// Splits samples without metadata into runs, as several `--cmd` write, wherever the label changes or the
+/// sample count starts over.
+fn by_label(samples: Vec<Value>) -> Vec<(Option<Value>, Vec<Value>)> {
+    let mut runs: Vec<(Option<Value>, Vec<Value>)> = Vec::new();
+
+    for sample in samples {
+        let continues = |last: &Value| {
+            last["label"] == sample["label"] && last["sample"].as_u64() < sample["sample"].as_u64()
+        };
+
+        match runs.last_mut() {
+            Some((_, run)) if run.last().is_some_and(continues) => run.push(sample),
+            _ => runs.push((None, vec![sample])),
+        }
+    }
+
+    runs
+}
+
+fn to_bytes(value: &mut Value, fields: &[&str], unit: MemUnit) {
+    for field in fields {
+        if let Some(memory) = value.get_mut(*field) {
+            if let Some(number) = memory.as_f64() {
+                *memory = Value::from(unit.to_bytes(number));
+            }
+        }
+    }
+}
+
+/// What metadata can be worked out from the samples alone.
+fn recover(samples: &[Samples]) -> Metadata {
+    let mut peaks = Peaks::default();
+
+    for sample in samples {
+        peaks.update(sample);
+    }
+
+    let first = samples.first();
+
+    Metadata {
+        uuid: first
+            .and_then(|sample| sample.uuid)
+            .unwrap_or_else(Uuid::new_v4),
+        label: first.map(|sample| sample.label.clone()).unwrap_or_default(),
+        start_time: first.map(|sample| sample.timestamp),
+        end_time: samples.last().map(|sample| sample.timestamp),
+        peak_cpu: peaks.cpu,
+        peak_mem: Memory {
+            bytes: peaks.mem,
+            unit: MemUnit::Bytes,
+        },
+        peak_virt_mem: Memory {
+            bytes: peaks.virt_mem,
+            unit: MemUnit::Bytes,
+        },
+        disk_read: peaks.disk_read,
+        disk_write: peaks.disk_write,
+        ..Metadata::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample(label: &str, sample: u64) -> Value {
+        json!({ "label": label, "sample": sample })
+    }
+
+    fn sizes(runs: &[(Option<Value>, Vec<Value>)]) -> Vec<usize> {
+        runs.iter().map(|(_, samples)| samples.len()).collect()
+    }
+
+    #[test]
+    fn runs_split_where_the_label_changes() {
+        let runs = by_label(vec![
+            sample("a", 0),
+            sample("a", 1),
+            sample("b", 0),
+            sample("b", 1),
+            sample("b", 2),
+        ]);
+
+        assert_eq!(sizes(&runs), [2, 3]);
+        assert!(runs.iter().all(|(metadata, _)| metadata.is_none()));
+    }
+
+    #[test]
+    fn runs_split_where_the_sample_count_starts_over() {
+        let runs = by_label(vec![sample("a", 0), sample("a", 1), sample("a", 0)]);
+
+        assert_eq!(sizes(&runs), [2, 1]);
+    }
+
+    #[test]
+    fn ndjson_is_read_back_in_the_input_unit() {
+        let path = std::env::temp_dir().join(format!("peek-test-{}.ndjson", Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "{\"label\":\"a\",\"sample\":0,\"mem\":1.5,\"virt_mem\":2}\n\n\
+             {\"label\":\"a\",\"sample\":1,\"mem\":3,\"virt_mem\":4}\n",
+        )
+        .unwrap();
+
+        let runs = load(&path, MemUnit::Mib).unwrap();
+        let (metadata, samples) = &runs[0];
+
+        assert_eq!(runs.len(), 1);
+        assert_eq!(samples[0].mem.bytes, 3 << 19);
+        assert_eq!(samples[1].virt_mem.bytes, 4 << 20);
+        // Recovered from the samples rather than read.
+        assert_eq!(metadata.label, "a");
+        assert_eq!(metadata.peak_mem.bytes, 3 << 20);
+    }
+
+    #[test]
+    fn other_json_is_not_peek_output() {
+        let path = std::env::temp_dir().join(format!("peek-test-{}.json", Uuid::new_v4()));
+        std::fs::write(&path, "42").unwrap();
+
+        let err = load(&path, MemUnit::Bytes).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!("`{}` is not the output of `peek`", path.display())
+        );
+    }
+}
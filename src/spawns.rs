@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use sysinfo::{Process, ProcessExt};
+
+/// A child showing up in or dropping out of the process tree, for `--trace-spawns`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct SpawnEvent {
+    /// When the sample that first saw the change was taken, in unix epoch milliseconds.
+    pub timestamp: u64,
+    pub event: Kind,
+    pub pid: usize,
+    /// The process it was started by, `null` if it had been reparented away by the time it was seen.
+    pub parent: Option<usize>,
+    pub name: String,
+}
+
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Spawn,
+    Exit,
+}
+
+/// Tells which processes in the tree are new and which are gone since the last sample.
+///
+/// Only what's alive while sampling is seen, so a child that starts and exits between two samples never
+/// shows up at all.
+#[derive(Default)]
+pub struct Tracker {
+    /// Name and parent of every process in the tree last time, by pid.
+    known: HashMap<usize, (String, Option<usize>)>,
+}
+
+impl Tracker {
+    /// Compares `processes`, the tree below `root`, against the last call. The root itself is left out, it
+    /// is there from the first sample to the last.
+    pub fn update(
+        &mut self,
+        processes: &[&Process],
+        root: usize,
+        timestamp: u64,
+    ) -> Vec<SpawnEvent> {
+        let current: HashMap<usize, (String, Option<usize>)> = processes
+            .iter()
+            .map(|process| {
+                let parent = process.parent().map(usize::from);
+                (
+                    usize::from(process.pid()),
+                    (process.name().to_string(), parent),
+                )
+            })
+            .filter(|(pid, _)| *pid != root)
+            .collect();
+
+        let mut events: Vec<SpawnEvent> = current
+            .iter()
+            .filter(|(pid, _)| !self.known.contains_key(pid))
+            .map(|(&pid, (name, parent))| SpawnEvent {
+                timestamp,
+                event: Kind::Spawn,
+                pid,
+                parent: *parent,
+                name: name.clone(),
+            })
+            .collect();
+
+        events.extend(
+            self.known
+                .iter()
+                .filter(|(pid, _)| !current.contains_key(pid))
+                .map(|(&pid, (name, parent))| SpawnEvent {
+                    timestamp,
+                    event: Kind::Exit,
+                    pid,
+                    parent: *parent,
+                    name: name.clone(),
+                }),
+        );
+
+        // Hash map order isn't meaningful, pids at least roughly follow the order processes started in.
+        events.sort_by_key(|event| event.pid);
+
+        self.known = current;
+
+        events
+    }
+}
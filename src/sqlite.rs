@@ -0,0 +1,96 @@
+use crate::{row, Report};
+use anyhow::{Context, Result};
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection, Transaction};
+use serde_json::Map;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Appends the runs to the database at `path`, creating it and its schema if needed.
+///
+/// Each run's metadata goes into `runs`, keyed by its `uuid`, and every sample into `samples`.
+/// Columns are derived from the serialized fields, so new columns get added to existing
+/// databases as fields are added.
+pub fn write(path: &Path, reports: &[Report]) -> Result<()> {
+    let mut connection = Connection::open(path)
+        .with_context(|| format!("failed to open database `{}`", path.display()))?;
+
+    let transaction = connection.transaction()?;
+
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS runs (uuid TEXT PRIMARY KEY NOT NULL);
+         CREATE TABLE IF NOT EXISTS samples (uuid TEXT NOT NULL REFERENCES runs (uuid));",
+    )?;
+
+    // Read once and kept up to date as columns are added, rather than asked for again with every row.
+    let mut runs = columns(&transaction, "runs")?;
+    let mut samples = columns(&transaction, "samples")?;
+
+    for report in reports {
+        insert(&transaction, "runs", &mut runs, &row(report.metadata)?)?;
+
+        for sample in report.samples {
+            insert(&transaction, "samples", &mut samples, &row(sample)?)?;
+        }
+    }
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+/// The columns `table` already has.
+fn columns(transaction: &Transaction, table: &str) -> Result<HashSet<String>> {
+    let columns = transaction
+        .prepare(&format!("SELECT name FROM pragma_table_info('{table}')"))?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(columns)
+}
+
+/// Inserts `row` into `table`, first adding whichever of its fields aren't yet among the `existing` columns.
+fn insert(
+    transaction: &Transaction,
+    table: &str,
+    existing: &mut HashSet<String>,
+    row: &Map<String, serde_json::Value>,
+) -> Result<()> {
+    for column in row.keys() {
+        if !existing.contains(column) {
+            transaction.execute(&format!("ALTER TABLE {table} ADD COLUMN \"{column}\""), [])?;
+            existing.insert(column.clone());
+        }
+    }
+
+    let columns = row
+        .keys()
+        .map(|column| format!("\"{column}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let placeholders = vec!["?"; row.len()].join(", ");
+
+    transaction
+        .prepare_cached(&format!(
+            "INSERT INTO {table} ({columns}) VALUES ({placeholders})"
+        ))?
+        .execute(params_from_iter(row.values().map(to_sql)))?;
+
+    Ok(())
+}
+
+fn to_sql(value: &serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(bool) => Value::Integer(i64::from(*bool)),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(integer) => Value::Integer(integer),
+            None => Value::Real(number.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(string) => Value::Text(string.clone()),
+        // Nested values are kept as JSON, which SQLite's JSON functions can still query.
+        serde_json::Value::Array(_) | serde_json::Value::Object(_) => {
+            Value::Text(value.to_string())
+        }
+    }
+}
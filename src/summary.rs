@@ -0,0 +1,207 @@
+use crate::{MemUnit, Peaks, Samples};
+
+/// Aggregate statistics over a single metric.
+pub struct Stats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub p95: f64,
+}
+
+impl Stats {
+    fn new(mut values: Vec<f64>) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+
+        values.sort_by(f64::total_cmp);
+
+        // Nearest-rank percentile.
+        let rank = (values.len() as f64 * 0.95).ceil() as usize;
+
+        Some(Self {
+            min: values[0],
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            p95: values[rank.saturating_sub(1)],
+        })
+    }
+}
+
+/// Statistics of each metric across all samples, as its name, its stats if there were any samples and the
+/// number of decimals worth showing.
+pub fn metrics(samples: &[Samples], unit: MemUnit) -> Vec<(&'static str, Option<Stats>, usize)> {
+    // Disk usage is cumulative, so the deltas between samples are what's interesting.
+    fn deltas(samples: &[Samples], total: fn(&Samples) -> u64) -> Vec<f64> {
+        samples
+            .windows(2)
+            .map(|pair| total(&pair[1]).saturating_sub(total(&pair[0])) as f64)
+            .collect()
+    }
+
+    let mem_precision = mem_precision(unit);
+
+    let metrics: [(&'static str, Vec<f64>, usize); 5] = [
+        ("cpu", samples.iter().map(|s| s.cpu as f64).collect(), 2),
+        (
+            "mem",
+            samples.iter().map(|s| unit.convert(s.mem.bytes)).collect(),
+            mem_precision,
+        ),
+        (
+            "virt_mem",
+            samples
+                .iter()
+                .map(|s| unit.convert(s.virt_mem.bytes))
+                .collect(),
+            mem_precision,
+        ),
+        ("disk_read", deltas(samples, |s| s.disk_read), 0),
+        ("disk_write", deltas(samples, |s| s.disk_write), 0),
+    ];
+
+    let mut metrics: Vec<_> = metrics
+        .into_iter()
+        .map(|(name, values, precision)| (name, Stats::new(values), precision))
+        .collect();
+
+    let smoothed: Vec<f64> = samples
+        .iter()
+        .filter_map(|s| s.cpu_smoothed.map(f64::from))
+        .collect();
+
+    // Only with `--smooth`, shown right after the raw `cpu` it evens out.
+    if !smoothed.is_empty() {
+        metrics.insert(1, ("cpu_smoothed", Stats::new(smoothed), 2));
+    }
+
+    metrics
+}
+
+/// Prints min, max, mean and p95 of each metric across all samples, followed by the peaks, to stderr.
+pub fn print(samples: &[Samples], peaks: &Peaks, unit: MemUnit) {
+    let mem_precision = mem_precision(unit);
+
+    eprintln!(
+        "{:<14}{:>16}{:>16}{:>16}{:>16}",
+        "metric", "min", "max", "mean", "p95"
+    );
+
+    for (name, stats, precision) in metrics(samples, unit) {
+        match stats {
+            Some(stats) => eprintln!(
+                "{name:<14}{:>16.precision$}{:>16.precision$}{:>16.precision$}{:>16.precision$}",
+                stats.min, stats.max, stats.mean, stats.p95
+            ),
+            None => eprintln!("{name:<14}{:>16}{:>16}{:>16}{:>16}", "-", "-", "-", "-"),
+        }
+    }
+
+    eprintln!();
+    eprintln!("{:<14}{:>16.2}", "peak_cpu", peaks.cpu);
+    eprintln!(
+        "{:<14}{:>16.mem_precision$}",
+        "peak_mem",
+        unit.convert(peaks.mem)
+    );
+    eprintln!(
+        "{:<14}{:>16.mem_precision$}",
+        "peak_virt_mem",
+        unit.convert(peaks.virt_mem)
+    );
+}
+
+/// Whole bytes need no decimals, larger units would round most processes down to nothing.
+fn mem_precision(unit: MemUnit) -> usize {
+    if unit == MemUnit::Bytes {
+        0
+    } else {
+        2
+    }
+}
+
+/// Prints how much the mean of each metric varied between `runs`, repeats of the same program, as the mean
+/// over the runs, its standard deviation and their ratio, the coefficient of variation, to stderr.
+pub fn print_variation(runs: &[&[Samples]], unit: MemUnit) {
+    eprintln!("{:<14}{:>16}{:>16}{:>16}", "metric", "mean", "stddev", "cv");
+
+    let metrics: Vec<_> = runs.iter().map(|samples| metrics(samples, unit)).collect();
+
+    let Some(first) = metrics.first() else {
+        return;
+    };
+
+    for (i, (name, _, precision)) in first.iter().enumerate() {
+        // Runs without samples, like those never started after an interrupt, have nothing to add.
+        let means: Vec<f64> = metrics
+            .iter()
+            .filter_map(|metrics| metrics[i].1.as_ref())
+            .map(|stats| stats.mean)
+            .collect();
+
+        if means.len() < 2 {
+            eprintln!("{name:<14}{:>16}{:>16}{:>16}", "-", "-", "-");
+            continue;
+        }
+
+        let n = means.len() as f64;
+        let mean = means.iter().sum::<f64>() / n;
+        let stddev = (means.iter().map(|m| (m - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+
+        // Undefined for a metric that stayed at 0 throughout.
+        let cv = if mean == 0.0 {
+            "-".to_owned()
+        } else {
+            format!("{:.1}%", stddev / mean * 100.0)
+        };
+
+        eprintln!("{name:<14}{mean:>16.precision$}{stddev:>16.precision$}{cv:>16}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_of_nothing() {
+        assert!(Stats::new(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn stats_take_the_nearest_rank_p95() {
+        let stats = Stats::new((1..=20).rev().map(f64::from).collect()).unwrap();
+
+        assert_eq!(stats.min, 1.0);
+        assert_eq!(stats.max, 20.0);
+        assert_eq!(stats.mean, 10.5);
+        assert_eq!(stats.p95, 19.0);
+    }
+
+    #[test]
+    fn p95_of_a_single_value_is_that_value() {
+        assert_eq!(Stats::new(vec![7.0]).unwrap().p95, 7.0);
+    }
+
+    #[test]
+    fn disk_stats_are_of_what_changed_between_samples() {
+        let samples: Vec<Samples> = [0, 100, 100, 400]
+            .into_iter()
+            .map(|read| Samples {
+                disk_read: read,
+                ..Samples::default()
+            })
+            .collect();
+        let metrics = metrics(&samples, MemUnit::Bytes);
+        let (_, stats, _) = metrics
+            .iter()
+            .find(|(name, ..)| *name == "disk_read")
+            .unwrap();
+        let stats = stats.as_ref().unwrap();
+
+        assert_eq!(
+            (stats.min, stats.max, stats.mean),
+            (0.0, 300.0, 400.0 / 3.0)
+        );
+    }
+}
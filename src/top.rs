@@ -0,0 +1,190 @@
+use crate::watch;
+use anyhow::Result;
+use clap::ValueEnum;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, SetAttribute};
+use crossterm::terminal::{Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use std::io::{Stderr, Write};
+use std::time::{Duration, Instant};
+use sysinfo::{Pid, ProcessExt, ProcessRefreshKind, System, SystemExt};
+
+/// List every running process, refreshed live, and pick one to sample like `peek --pid <PID> --watch`.
+#[derive(clap::Args)]
+pub struct Args {
+    /// What to sort processes by, highest first. Can be switched with `s` while running.
+    #[arg(value_enum, long, default_value_t)]
+    sort: Sort,
+    /// Milliseconds between refreshes.
+    #[arg(long, short, default_value = "1000", value_parser = clap::value_parser!(u64).range(1..))]
+    interval: u64,
+}
+
+#[derive(Copy, Clone, Default, ValueEnum)]
+enum Sort {
+    #[default]
+    Cpu,
+    Mem,
+}
+
+struct Row {
+    pid: Pid,
+    name: String,
+    cpu: f32,
+    mem: u64,
+}
+
+/// Puts the terminal back the way it was, however `run` returns.
+struct Screen(Stderr);
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        let mut stderr = std::io::stderr();
+        crossterm::terminal::enable_raw_mode()?;
+        crossterm::execute!(stderr, EnterAlternateScreen, Hide)?;
+        Ok(Self(stderr))
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = crossterm::execute!(self.0, Show, LeaveAlternateScreen);
+        let _ = crossterm::terminal::disable_raw_mode();
+    }
+}
+
+/// Shows the list on stderr until a process is picked with enter, returning its pid, or `None` when left
+/// with `q`, escape or Ctrl-C.
+pub fn run(args: &Args) -> Result<Option<usize>> {
+    let mut system = System::new();
+    let mut sort = args.sort;
+    let interval = Duration::from_millis(args.interval);
+
+    // Kept by pid rather than position, so the selection doesn't jump as the order changes.
+    let mut selected: Option<Pid> = None;
+
+    let mut screen = Screen::enter()?;
+
+    // Refreshed once up front, as CPU usage needs a previous refresh to be measured against.
+    system.refresh_processes_specifics(ProcessRefreshKind::new().with_cpu());
+    std::thread::sleep(System::MINIMUM_CPU_UPDATE_INTERVAL);
+
+    loop {
+        system.refresh_processes_specifics(ProcessRefreshKind::new().with_cpu());
+
+        let mut rows: Vec<Row> = system
+            .processes()
+            .values()
+            .map(|process| Row {
+                pid: process.pid(),
+                name: process.name().to_string(),
+                cpu: process.cpu_usage(),
+                mem: process.memory(),
+            })
+            .collect();
+
+        match sort {
+            Sort::Cpu => rows.sort_by(|a, b| b.cpu.total_cmp(&a.cpu)),
+            Sort::Mem => rows.sort_by_key(|row| std::cmp::Reverse(row.mem)),
+        }
+
+        let mut index = selected
+            .and_then(|pid| rows.iter().position(|row| row.pid == pid))
+            .unwrap_or_default();
+
+        let refreshed = Instant::now();
+
+        // Redrawn on every key press, and the processes refreshed again once the interval is up.
+        loop {
+            selected = rows.get(index).map(|row| row.pid);
+            draw(&mut screen.0, &rows, index, sort)?;
+
+            let Some(wait) = interval.checked_sub(refreshed.elapsed()) else {
+                break;
+            };
+
+            if !crossterm::event::poll(wait)? {
+                break;
+            }
+
+            let Event::Key(key) = crossterm::event::read()? else {
+                continue;
+            };
+
+            // Windows also reports releasing a key.
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(None)
+                }
+                KeyCode::Enter => return Ok(selected.map(usize::from)),
+                KeyCode::Up | KeyCode::Char('k') => index = index.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => {
+                    index = (index + 1).min(rows.len().saturating_sub(1));
+                }
+                KeyCode::Char('s') => {
+                    sort = match sort {
+                        Sort::Cpu => Sort::Mem,
+                        Sort::Mem => Sort::Cpu,
+                    };
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn draw(stderr: &mut Stderr, rows: &[Row], selected: usize, sort: Sort) -> Result<()> {
+    let (_, height) = crossterm::terminal::size()?;
+
+    // Two lines go to the header, and the list scrolls to keep the selection in view.
+    let visible = usize::from(height).saturating_sub(2).max(1);
+    let first = selected.saturating_sub(visible - 1);
+
+    crossterm::queue!(stderr, MoveTo(0, 0), Clear(ClearType::All))?;
+
+    let sorted = match sort {
+        Sort::Cpu => "cpu",
+        Sort::Mem => "mem",
+    };
+
+    write!(
+        stderr,
+        "sorted by {sorted}  up/down to select, enter to peek, s to sort by the other, q to quit\r\n"
+    )?;
+    write!(
+        stderr,
+        "{:>8}  {:<24}{:>10}{:>12}",
+        "pid", "name", "cpu", "mem"
+    )?;
+
+    // Each line ends where the next starts, as a newline after the last would scroll the header away.
+    for (i, row) in rows.iter().enumerate().skip(first).take(visible) {
+        write!(stderr, "\r\n")?;
+
+        if i == selected {
+            crossterm::queue!(stderr, SetAttribute(Attribute::Reverse))?;
+        }
+
+        let name: String = row.name.chars().take(23).collect();
+
+        write!(
+            stderr,
+            "{:>8}  {name:<24}{:>9.1}%{:>12}",
+            row.pid,
+            row.cpu,
+            watch::bytes(row.mem)
+        )?;
+
+        crossterm::queue!(stderr, SetAttribute(Attribute::Reset))?;
+    }
+
+    stderr.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use crate::{MemUnit, Samples};
+use anyhow::Result;
+use crossterm::cursor::MoveToPreviousLine;
+use crossterm::terminal::{Clear, ClearType};
+use std::io::{Stderr, Write};
+
+/// Live view of the latest sample, redrawn in place on stderr so it never mixes with the output.
+pub struct Watch {
+    stderr: Stderr,
+    /// Lines drawn last time, which get cleared before drawing again.
+    lines: u16,
+    /// Unit of `mem` and `virt_mem`, picked per value when not given.
+    mem_unit: Option<MemUnit>,
+}
+
+impl Watch {
+    pub fn new(mem_unit: Option<MemUnit>) -> Self {
+        Self {
+            stderr: std::io::stderr(),
+            lines: 0,
+            mem_unit,
+        }
+    }
+
+    pub fn draw(&mut self, sample: &Samples) -> Result<()> {
+        let lines = [
+            match sample.pid {
+                Some(pid) => format!("{} (pid {pid})  sample {}", sample.name, sample.sample),
+                None => format!("{}  sample {}", sample.name, sample.sample),
+            },
+            format!("cpu         {:>10.2}%", sample.cpu),
+            format!("mem         {:>11}", self.memory(sample.mem.bytes)),
+            format!("virt_mem    {:>11}", self.memory(sample.virt_mem.bytes)),
+            format!(
+                "swap        {:>11}",
+                sample
+                    .swap
+                    .map_or_else(|| "-".to_owned(), |swap| self.memory(swap.bytes))
+            ),
+            format!("disk_read   {:>11}/s", bytes(sample.disk_read_rate as u64)),
+            format!("disk_write  {:>11}/s", bytes(sample.disk_write_rate as u64)),
+        ];
+
+        if self.lines > 0 {
+            crossterm::queue!(
+                self.stderr,
+                MoveToPreviousLine(self.lines),
+                Clear(ClearType::FromCursorDown)
+            )?;
+        }
+
+        for line in &lines {
+            writeln!(self.stderr, "{line}")?;
+        }
+
+        self.stderr.flush()?;
+        self.lines = lines.len() as u16;
+
+        Ok(())
+    }
+
+    fn memory(&self, value: u64) -> String {
+        match self.mem_unit {
+            Some(MemUnit::Bytes) => format!("{value} B"),
+            Some(unit) => format!("{:.1} {}", unit.convert(value), unit.suffix()),
+            None => bytes(value),
+        }
+    }
+}
+
+/// `bytes` in the biggest binary unit that keeps it at or above 1, like `1.5 GiB`.
+pub fn bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{value:.1} {}", UNITS[unit])
+}